@@ -1,4 +1,6 @@
-use crate::gadgets::components::GadgetType;
+use crate::gadgets::components::{
+    CollectibleType, GadgetType, MagnetGadget, ReactivatorField, SpawnedFromCard, UpgradeLevel,
+};
 use crate::gadgets::resources::GameResources;
 use crate::gameplay::components::CardBorder;
 use avian2d::prelude::*;
@@ -13,34 +15,115 @@ use bevy::text::TextBounds;
 use bevy_bundled_observers::observers;
 use bevy_simple_subsecond_system::prelude::*;
 use bevy_vector_shapes::prelude::*;
+use serde::{Deserialize, Serialize};
 use crate::game_ui::components::Forbidden;
+use crate::replay::resources::ReplayPlayback;
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq, Copy, Reflect, Default)]
+#[derive(
+    Debug, Clone, Hash, PartialEq, Eq, PartialOrd, Ord, Copy, Reflect, Default, Serialize, Deserialize,
+)]
 pub enum ShopCardType {
     #[default]
     OneMoreBallCard,
     MoreBallsCard,
     SquareBlockCard,
     WideBlockCard,
+    TriangleBlockCard,
     BumperCard,
     CoinBumperCard,
     HighFrictionBlockCard,
+    FlipperCard,
     MagnetCard,
     ReactivateLaserBridgeCard,
     GravityReverserCard,
     MultiBallCard,
+    PortalCard,
     RecycleGadgetCard,
     RearrangeGadgetCard,
 }
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Reflect, Serialize, Deserialize)]
+pub enum CardRarity {
+    Common,
+    Uncommon,
+    Rare,
+}
+
+impl CardRarity {
+    pub const ALL: [CardRarity; 3] = [CardRarity::Common, CardRarity::Uncommon, CardRarity::Rare];
+
+    /// Color of the card frame.
+    pub fn color(&self) -> Color {
+        match self {
+            CardRarity::Common => tailwind::GRAY_100.into(),
+            CardRarity::Uncommon => tailwind::SKY_400.into(),
+            CardRarity::Rare => tailwind::AMBER_400.into(),
+        }
+    }
+}
+
 impl ShopCardType {
+    pub const ALL: [ShopCardType; 16] = [
+        ShopCardType::OneMoreBallCard,
+        ShopCardType::MoreBallsCard,
+        ShopCardType::SquareBlockCard,
+        ShopCardType::WideBlockCard,
+        ShopCardType::TriangleBlockCard,
+        ShopCardType::BumperCard,
+        ShopCardType::CoinBumperCard,
+        ShopCardType::HighFrictionBlockCard,
+        ShopCardType::FlipperCard,
+        ShopCardType::MagnetCard,
+        ShopCardType::ReactivateLaserBridgeCard,
+        ShopCardType::GravityReverserCard,
+        ShopCardType::MultiBallCard,
+        ShopCardType::PortalCard,
+        ShopCardType::RecycleGadgetCard,
+        ShopCardType::RearrangeGadgetCard,
+    ];
+
+    /// Cards that act on a gadget that is already placed.
+    pub fn needs_placed_gadget(&self) -> bool {
+        matches!(
+            self,
+            ShopCardType::RecycleGadgetCard | ShopCardType::RearrangeGadgetCard
+        )
+    }
+
+    /// Spawns the gadget of this card with the balance values of the upgrade `level`, `None` for
+    /// cards that are not a gadget.
+    pub fn spawn_gadget(
+        &self,
+        commands: &mut Commands,
+        game_resources: &GameResources,
+        level: usize,
+    ) -> Option<Entity> {
+        let entity = self.get_gadget_type()?.spawn_widget(commands);
+        let mut entity_commands = commands.entity(entity);
+        entity_commands.insert((SpawnedFromCard(*self), UpgradeLevel(level)));
+        if let Some(gadget) = game_resources.gadget_for_level(self, level) {
+            entity_commands.insert(gadget);
+        }
+        if let Some(points) = game_resources.points_for_level(self, level) {
+            entity_commands.insert(points);
+        }
+        Some(entity)
+    }
+
     pub fn get_gadget_type(&self) -> Option<GadgetType> {
         match self {
             ShopCardType::SquareBlockCard => Some(GadgetType::SquareBlockType),
             ShopCardType::WideBlockCard => Some(GadgetType::WideBlockType),
+            ShopCardType::TriangleBlockCard => Some(GadgetType::TriangleBlockType),
             ShopCardType::BumperCard => Some(GadgetType::BumperType),
             ShopCardType::CoinBumperCard => Some(GadgetType::CoinBumperType),
             ShopCardType::GravityReverserCard => Some(GadgetType::GravityReverseType),
+            ShopCardType::MagnetCard => Some(GadgetType::MagnetType),
+            ShopCardType::HighFrictionBlockCard => Some(GadgetType::HighFrictionBlockType),
+            ShopCardType::FlipperCard => Some(GadgetType::FlipperType),
+            ShopCardType::MultiBallCard => Some(GadgetType::MultiBallType),
+            ShopCardType::PortalCard => Some(GadgetType::PortalType),
+            ShopCardType::ReactivateLaserBridgeCard => Some(GadgetType::ReactivatorType),
             _ => None,
         }
     }
@@ -54,18 +137,30 @@ impl ShopCardType {
 #[component(on_add=ShopCard::on_add)]
 pub struct ShopCard {
     pub card_type: ShopCardType,
+    /// upgrade level the card is shown with
+    pub level: usize,
 }
 
 impl ShopCard {
     pub fn new(card_type: ShopCardType) -> Self {
-        Self {card_type }
+        Self { card_type, level: 0 }
+    }
+
+    pub fn with_level(mut self, level: usize) -> Self {
+        self.level = level;
+        self
     }
 
     fn on_add(mut world: DeferredWorld, context: HookContext) {
-        let card = world.get::<ShopCard>(context.entity).unwrap().card_type.clone();
+        let shop_card = *world.get::<ShopCard>(context.entity).unwrap();
+        let card = shop_card.card_type;
         let game_resource = world.get_resource::<GameResources>().unwrap();
-        let title = game_resource.card_title(&card);
-        let description = game_resource.card_description(&card);
+        let mut title = game_resource.card_title(&card);
+        if shop_card.level > 0 {
+            title = format!("{} +{}", title, shop_card.level);
+        }
+        let description = game_resource.card_description(&card, shop_card.level);
+        let frame_color = Self::frame_color(game_resource, &card);
 
         world
             .commands()
@@ -117,7 +212,7 @@ impl ShopCard {
                         .rect(Vec2::new(140., 200.))
                         .insert(ChildOf(context.entity));
 
-                    shapes.color = tailwind::GRAY_100.into();
+                    shapes.color = frame_color;
                     shapes.hollow = true;
                     shapes
                         .rect(Vec2::new(140., 200.))
@@ -147,15 +242,28 @@ impl ShopCard {
     pub fn on_out(
         trigger: Trigger<Pointer<Out>>,
         q_children: Query<&Children>,
+        q_shop_card: Query<&ShopCard>,
         mut q_card_border: Query<&mut ShapeFill, With<CardBorder>>,
+        game_resources: Res<GameResources>,
     ) {
+        let Ok(shop_card) = q_shop_card.get(trigger.target) else {
+            return;
+        };
         for child in q_children.get(trigger.target).unwrap().iter() {
             if let Ok(mut shape_fill) = q_card_border.get_mut(child) {
-                shape_fill.color = tailwind::GRAY_100.into();
+                shape_fill.color = Self::frame_color(&game_resources, &shop_card.card_type);
                 return;
             }
         }
     }
+
+    /// Cards the shop never offers get the common frame.
+    fn frame_color(game_resources: &GameResources, card: &ShopCardType) -> Color {
+        game_resources
+            .card_rarity(card)
+            .unwrap_or(CardRarity::Common)
+            .color()
+    }
 }
 
 #[derive(Component, Debug, Clone)]
@@ -216,6 +324,14 @@ impl CardIcon {
                     ..default()
                 },));
             }
+            ShopCardType::TriangleBlockCard => {
+                let image = game_resources.gadget_images[&GadgetType::TriangleBlockType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
             ShopCardType::BumperCard { .. } => {
                 let image = game_resources.gadget_images[&GadgetType::BumperType].clone();
                 world.commands().entity(context.entity).insert((Sprite {
@@ -232,9 +348,47 @@ impl CardIcon {
                     ..default()
                 },));
             }
-            ShopCardType::HighFrictionBlockCard => {}
-            ShopCardType::MagnetCard => {}
-            ShopCardType::ReactivateLaserBridgeCard => {}
+            ShopCardType::FlipperCard => {
+                let image = game_resources.gadget_images[&GadgetType::FlipperType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    custom_size: Some(Vec2::new(80.0, 20.0)),
+                    ..default()
+                },));
+            }
+            ShopCardType::HighFrictionBlockCard => {
+                let slice_border = 30.0;
+                let image_mode = SpriteImageMode::Sliced(TextureSlicer {
+                    border: BorderRect::all(slice_border),
+                    center_scale_mode: SliceScaleMode::Stretch,
+                    ..default()
+                });
+                let image = game_resources.gadget_images[&GadgetType::HighFrictionBlockType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    image_mode,
+                    custom_size: Some(Vec2::new(80.0, 40.0)),
+                    ..default()
+                },));
+            }
+            ShopCardType::MagnetCard => {
+                let image = game_resources.gadget_images[&GadgetType::MagnetType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    color: MagnetGadget::TINT,
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
+            ShopCardType::ReactivateLaserBridgeCard => {
+                let image = game_resources.gadget_images[&GadgetType::ReactivatorType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    color: ReactivatorField::TINT,
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
             ShopCardType::GravityReverserCard => {
                 let image = game_resources.reverse_gravity_image.clone();
                 world.commands().entity(context.entity).insert((Sprite {
@@ -243,9 +397,46 @@ impl CardIcon {
                     ..default()
                 },));
             }
-            ShopCardType::MultiBallCard => {}
-            ShopCardType::RecycleGadgetCard => {}
-            ShopCardType::RearrangeGadgetCard => {}
+            ShopCardType::MultiBallCard => {
+                let image = game_resources.gadget_images[&GadgetType::MultiBallType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
+            ShopCardType::PortalCard => {
+                let image = game_resources.gadget_images[&GadgetType::PortalType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
+            ShopCardType::RecycleGadgetCard => {
+                let image = game_resources.collectibles_images[&CollectibleType::CoinType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
+            ShopCardType::RearrangeGadgetCard => {
+                let slice_border = 30.0;
+                let image_mode = SpriteImageMode::Sliced(TextureSlicer {
+                    border: BorderRect::all(slice_border),
+                    center_scale_mode: SliceScaleMode::Stretch,
+                    ..default()
+                });
+                let image = game_resources.gadget_images[&GadgetType::SquareBlockType].clone();
+                world.commands().entity(context.entity).insert((Sprite {
+                    image,
+                    image_mode,
+                    color: Color::WHITE.with_alpha(0.5),
+                    custom_size: Some(Vec2::new(default_size, default_size)),
+                    ..default()
+                },));
+            }
         }
     }
 }
@@ -261,7 +452,14 @@ impl OnClickOnShopCard {
         );
     }
 
-    fn on_click(trigger: Trigger<Pointer<Click>>, mut commands: Commands) {
+    fn on_click(
+        trigger: Trigger<Pointer<Click>>,
+        mut commands: Commands,
+        playback: Option<Res<ReplayPlayback>>,
+    ) {
+        if playback.is_some() {
+            return;
+        }
         commands.trigger_targets(OnClickOnShopCard, trigger.target);
     }
 }
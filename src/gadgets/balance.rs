@@ -0,0 +1,117 @@
+use crate::cards::components::{CardRarity, ShopCardType};
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Balance values for all cards, loaded from `assets/config/game.balance.ron`.
+#[derive(Asset, TypePath, Debug, Deserialize)]
+pub struct BalanceConfig {
+    /// half extents of the play area
+    pub play_area: (f32, f32),
+    /// balls at the start of every level
+    pub balls_per_level: usize,
+    /// balls added by the MoreBalls card
+    pub extra_balls: usize,
+    pub cards: HashMap<ShopCardType, CardDefinition>,
+    /// how likely the shop picks a card of a rarity, shifted by the current level
+    pub rarity_weights: HashMap<CardRarity, RarityWeight>,
+    /// after this many shops without a rare card the next one offers at least one
+    pub rare_pity: usize,
+    /// price of taking a card out of the deck in the shop
+    pub card_removal_price: usize,
+    /// price of the first upgrade of a card, every further level costs this much more
+    pub card_upgrade_price: usize,
+    /// price of the first reroll of the shop offers in a visit
+    pub reroll_price: usize,
+    /// added to the reroll price for every reroll in the same visit
+    pub reroll_price_increase: usize,
+}
+
+/// Title and description may contain the placeholders `{points}`, `{activations}`,
+/// `{activation_time}` and `{extra_balls}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CardDefinition {
+    pub title: String,
+    pub description: String,
+    pub price: usize,
+    #[serde(default)]
+    pub activations: Option<usize>,
+    #[serde(default)]
+    pub points: Option<usize>,
+    /// in seconds
+    #[serde(default)]
+    pub activation_time: Option<f32>,
+    /// cards without a rarity are never offered in the shop
+    #[serde(default)]
+    pub rarity: Option<CardRarity>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RarityWeight {
+    pub base: f32,
+    pub per_level: f32,
+}
+
+impl RarityWeight {
+    pub fn at_level(&self, level: usize) -> f32 {
+        (self.base + self.per_level * level as f32).max(0.0)
+    }
+}
+
+impl BalanceConfig {
+    /// The config shipped with the game, used when the asset can not be loaded.
+    pub fn fallback() -> Self {
+        ron::from_str(include_str!("../../assets/config/game.balance.ron"))
+            .expect("the shipped balance config is valid")
+    }
+
+    /// Every card needs a definition, the game looks them up without a fallback.
+    pub fn validate(&self) -> Result<(), BalanceConfigLoaderError> {
+        match ShopCardType::ALL
+            .into_iter()
+            .find(|card| !self.cards.contains_key(card))
+        {
+            Some(card) => Err(BalanceConfigLoaderError::MissingCard(card)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BalanceConfigLoader;
+
+#[derive(Debug, Error)]
+pub enum BalanceConfigLoaderError {
+    #[error("could not read balance config: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not parse balance config: {0}")]
+    Ron(#[from] ron::error::SpannedError),
+    #[error("balance config has no card definition for {0:?}")]
+    MissingCard(ShopCardType),
+}
+
+impl AssetLoader for BalanceConfigLoader {
+    type Asset = BalanceConfig;
+    type Settings = ();
+    type Error = BalanceConfigLoaderError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let config = ron::de::from_bytes::<BalanceConfig>(&bytes)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["balance.ron"]
+    }
+}
@@ -1,13 +1,16 @@
+use crate::cards::components::ShopCardType;
 use crate::gadgets::resources::GameResources;
 use crate::gadgets::*;
 use crate::gameplay::components::*;
 use crate::gameplay::events::*;
 use crate::general::components::*;
 use avian2d::prelude::*;
+use bevy::color::palettes::tailwind;
 use bevy::ecs::component::HookContext;
 use bevy::ecs::world::DeferredWorld;
 use bevy::prelude::*;
 use bevy_bundled_observers::observers;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
 #[derive(Component, Debug, Clone, Reflect, Hash, PartialEq, Eq, Copy)]
@@ -18,23 +21,32 @@ pub enum GadgetType {
     CoinBumperType,
     BallCannonType,
     GravityReverseType,
+    MagnetType,
+    HighFrictionBlockType,
+    MultiBallType,
+    ReactivatorType,
+    TriangleBlockType,
+    PortalType,
+    FlipperType,
 }
 
 impl GadgetType {
+    /// Activations and points come from the balance config, see [`ShopCardType::spawn_gadget`].
     pub fn spawn_widget(&self, commands: &mut Commands) -> Entity {
         match self {
-            GadgetType::SquareBlockType => commands
-                .spawn((SquareBlock, Gadget::new(5), PointsOnHit::new(1)))
-                .id(),
-            GadgetType::WideBlockType => commands
-                .spawn((WideBlock, Gadget::new(5), PointsOnHit::new(1)))
-                .id(),
-            GadgetType::BumperType => commands
-                .spawn((Bumper, Gadget::new(3), PointsOnHit::new(3)))
-                .id(),
+            GadgetType::SquareBlockType => commands.spawn(SquareBlock).id(),
+            GadgetType::WideBlockType => commands.spawn(WideBlock).id(),
+            GadgetType::BumperType => commands.spawn(Bumper).id(),
             GadgetType::CoinBumperType => commands.spawn(CoinBumperGadget::default()).id(),
             GadgetType::BallCannonType => commands.spawn(BallCannon::bundle()).id(),
             GadgetType::GravityReverseType => commands.spawn(GravityReverseField::new()).id(),
+            GadgetType::MagnetType => commands.spawn(MagnetGadget::default()).id(),
+            GadgetType::HighFrictionBlockType => commands.spawn(HighFrictionBlock::default()).id(),
+            GadgetType::MultiBallType => commands.spawn(MultiBallGadget::default()).id(),
+            GadgetType::ReactivatorType => commands.spawn(ReactivatorField::default()).id(),
+            GadgetType::PortalType => commands.spawn(Portal::default()).id(),
+            GadgetType::FlipperType => commands.spawn(Flipper::default()).id(),
+            GadgetType::TriangleBlockType => commands.spawn(TriangleBlock::default()).id(),
         }
     }
 }
@@ -45,6 +57,14 @@ pub struct Preview;
 #[derive(Component, Debug, Reflect)]
 pub struct PlayerPlacedGadget;
 
+/// The card that was played to spawn this gadget.
+#[derive(Component, Debug, Reflect, Clone, Copy)]
+pub struct SpawnedFromCard(pub ShopCardType);
+
+/// How often the card of this gadget was upgraded in the shop.
+#[derive(Component, Debug, Reflect, Clone, Copy, Default)]
+pub struct UpgradeLevel(pub usize);
+
 #[derive(Component, Debug, Reflect)]
 pub struct Gadget {
     pub activations_left: usize,
@@ -166,13 +186,320 @@ impl CoinBumperGadget {
     }
 }
 
-#[derive(Component, Debug, Clone, Reflect, Hash, PartialEq, Eq)]
+#[derive(Component, Debug, Reflect)]
+#[require(Transform, Visibility)]
+#[require(Name::new("magnet"))]
+#[require(Gadget::new(1))]
+#[require(GadgetTint(MagnetGadget::TINT))]
+#[require(RigidBody::Static)]
+#[require(Restitution::new(1.2))]
+#[require(Collider::circle(29.0))]
+#[require(CollisionEventsEnabled)]
+#[require(CollisionLayers::new(GameLayer::GadgetsLayer, [
+GameLayer::PlayerBallLayer,
+GameLayer::GadgetsLayer,
+GameLayer::DefaultLayer
+]))]
+#[require(BounceOnHit)]
+#[component(on_add=MagnetGadget::on_add)]
+pub struct MagnetGadget {
+    pub active_time: Duration,
+    pub time_left: Duration,
+    pub radius: f32,
+    pub pull_speed: f32,
+}
+
+impl Default for MagnetGadget {
+    fn default() -> Self {
+        Self {
+            active_time: Duration::from_secs(5),
+            time_left: Duration::ZERO,
+            radius: 250.0,
+            pull_speed: 400.0,
+        }
+    }
+}
+
+impl MagnetGadget {
+    pub const TINT: Color = Color::Srgba(tailwind::PURPLE_300);
+
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        let game_resources = world.resource::<GameResources>();
+        let image = game_resources.gadget_images[&GadgetType::MagnetType].clone();
+        let active_time = game_resources.activation_time[&ShopCardType::MagnetCard].time;
+
+        world
+            .get_mut::<MagnetGadget>(context.entity)
+            .unwrap()
+            .active_time = active_time;
+
+        world
+            .commands()
+            .entity(context.entity)
+            .insert(observers![on_magnet_hit]);
+
+        world.commands().spawn((
+            ChildOf(context.entity),
+            SpriteVisualOf(context.entity),
+            Transform::from_scale(Vec3::splat(0.5)),
+            Sprite {
+                image,
+                color: MagnetGadget::TINT,
+                ..default()
+            },
+        ));
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.time_left.is_zero()
+    }
+}
+
+#[derive(Component, Debug, Reflect)]
+#[require(Transform, Visibility)]
+#[require(Name::new("multi_ball"))]
+#[require(Gadget::new(1))]
+#[require(Collider::circle(25.0))]
+#[require(Sensor)]
+#[require(CollisionEventsEnabled)]
+#[require(CollisionLayers::new(GameLayer::GadgetFieldsLayer, [GameLayer::PlayerBallLayer]))]
+#[component(on_add=MultiBallGadget::on_add)]
+pub struct MultiBallGadget {
+    pub extra_balls: usize,
+    /// Angle in radians between two neighbouring balls of the fan.
+    pub spread: f32,
+}
+
+impl Default for MultiBallGadget {
+    fn default() -> Self {
+        Self {
+            extra_balls: 2,
+            spread: 20.0_f32.to_radians(),
+        }
+    }
+}
+
+impl MultiBallGadget {
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        let game_resources = world.resource::<GameResources>();
+        let image = game_resources.gadget_images[&GadgetType::MultiBallType].clone();
+
+        world
+            .commands()
+            .entity(context.entity)
+            .insert(observers![on_hit_split_ball]);
+
+        world.commands().spawn((
+            ChildOf(context.entity),
+            SpriteVisualOf(context.entity),
+            Sprite {
+                image,
+                custom_size: Some(Vec2::splat(50.0)),
+                ..default()
+            },
+        ));
+    }
+
+    /// Rotation of the `index`-th extra ball, alternating between both sides of the original ball.
+    pub fn fan_angle(&self, index: usize) -> f32 {
+        let side = if index % 2 == 0 { 1.0 } else { -1.0 };
+        side * (index / 2 + 1) as f32 * self.spread
+    }
+}
+
+#[derive(Component, Debug, Reflect)]
+#[require(Transform, Visibility)]
+#[require(Name::new("reactivator_field"))]
+#[require(Gadget::new(1))]
+#[require(GadgetTint(ReactivatorField::TINT))]
+#[require(Collider::circle(25.0))]
+#[require(Sensor)]
+#[require(CollisionEventsEnabled)]
+#[require(CollisionLayers::new(GameLayer::GadgetFieldsLayer, [GameLayer::PlayerBallLayer]))]
+#[component(on_add=ReactivatorField::on_add)]
+pub struct ReactivatorField {
+    pub radius: f32,
+}
+
+impl Default for ReactivatorField {
+    fn default() -> Self {
+        Self { radius: 250.0 }
+    }
+}
+
+impl ReactivatorField {
+    pub const TINT: Color = Color::Srgba(tailwind::GREEN_400);
+
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        let game_resources = world.resource::<GameResources>();
+        let image = game_resources.gadget_images[&GadgetType::ReactivatorType].clone();
+
+        world
+            .commands()
+            .entity(context.entity)
+            .insert(observers![on_ball_entering_reactivator]);
+
+        world.commands().spawn((
+            ChildOf(context.entity),
+            SpriteVisualOf(context.entity),
+            Sprite {
+                image,
+                color: ReactivatorField::TINT,
+                custom_size: Some(Vec2::splat(50.0)),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Player controlled flipper. The body rotates around its own origin, which is the pivot, and the
+/// arm extends along the local x-axis.
+#[derive(Component, Debug, Reflect)]
+#[require(Transform, Visibility)]
+#[require(Name::new("flipper"))]
+#[require(RigidBody::Kinematic)]
+#[require(Restitution::new(0.3))]
+#[require(CollisionLayers::new(GameLayer::GadgetsLayer, [
+GameLayer::PlayerBallLayer,
+GameLayer::GadgetsLayer,
+GameLayer::DefaultLayer
+]))]
+#[require(CanBeRotated)]
+#[component(on_add=Flipper::on_add)]
+pub struct Flipper {
+    pub length: f32,
+    pub thickness: f32,
+    /// maximum angle the arm swings up, relative to the rest angle
+    pub max_angle: f32,
+    pub flip_stiffness: f32,
+    pub return_stiffness: f32,
+    pub max_angular_speed: f32,
+    /// rotation the flipper was placed with, the spring pulls the arm back here
+    pub rest_angle: f32,
+}
+
+impl Default for Flipper {
+    fn default() -> Self {
+        Self {
+            length: 140.0,
+            thickness: 30.0,
+            max_angle: 50f32.to_radians(),
+            flip_stiffness: 40.0,
+            return_stiffness: 15.0,
+            max_angular_speed: 25.0,
+            rest_angle: 0.0,
+        }
+    }
+}
+
+impl Flipper {
+    /// the arm always swings "up", so a flipper pointing to the left rotates clockwise
+    pub fn flip_direction(&self) -> f32 {
+        if self.rest_angle.cos() >= 0.0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        let flipper = world.get::<Flipper>(context.entity).unwrap();
+        let length = flipper.length;
+        let thickness = flipper.thickness;
+        let game_resources = world.resource::<GameResources>();
+        let image = game_resources.gadget_images[&GadgetType::FlipperType].clone();
+
+        world.commands().entity(context.entity).insert(Collider::capsule_endpoints(
+            thickness * 0.5,
+            Vec2::ZERO,
+            Vec2::new(length - thickness * 0.5, 0.0),
+        ));
+
+        world.commands().spawn((
+            ChildOf(context.entity),
+            SpriteVisualOf(context.entity),
+            Transform::from_xyz(length * 0.5 - thickness * 0.5, 0.0, 0.0),
+            Sprite {
+                image,
+                custom_size: Some(Vec2::new(length, thickness)),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// One end of a portal pair. The card places the entrance first, the exit is spawned as soon as the
+/// entrance has been placed.
+#[derive(Component, Debug, Reflect, Default)]
+#[require(Transform, Visibility)]
+#[require(Name::new("portal"))]
+#[require(Collider::circle(30.0))]
+#[require(Sensor)]
+#[require(CollisionEventsEnabled)]
+#[require(CollisionLayers::new(GameLayer::GadgetFieldsLayer, [GameLayer::PlayerBallLayer]))]
+#[require(CanBeRotated)]
+#[component(on_add=Portal::on_add)]
+pub struct Portal {
+    pub linked_portal: Option<Entity>,
+}
+
+impl Portal {
+    pub fn linked_to(entity: Entity) -> Self {
+        Self {
+            linked_portal: Some(entity),
+        }
+    }
+
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        let game_resources = world.resource::<GameResources>();
+        let image = game_resources.gadget_images[&GadgetType::PortalType].clone();
+
+        world
+            .commands()
+            .entity(context.entity)
+            .insert(observers![on_ball_entering_portal]);
+
+        world.commands().spawn((
+            ChildOf(context.entity),
+            SpriteVisualOf(context.entity),
+            Sprite {
+                image,
+                custom_size: Some(Vec2::splat(70.0)),
+                ..default()
+            },
+        ));
+    }
+}
+
+/// Keeps a ball that just went through a portal from being teleported straight back.
+#[derive(Component, Debug, Reflect)]
+pub struct PortalCooldown(pub Timer);
+
+impl Default for PortalCooldown {
+    fn default() -> Self {
+        Self(Timer::new(Duration::from_secs_f32(0.5), TimerMode::Once))
+    }
+}
+
+/// Marks a ball that was split off by a multi ball gadget, so it can't split on the same gadget again.
+#[derive(Component, Debug, Reflect)]
+pub struct SplitFrom(pub Entity);
+
+/// Base color of a gadget sprite, restored whenever the gadget is no longer highlighted.
+#[derive(Component, Debug, Reflect, Clone, Copy)]
+pub struct GadgetTint(pub Color);
+
+#[derive(Component, Debug, Clone, Reflect, Hash, PartialEq, Eq, Serialize, Deserialize)]
 #[require(RemainingRounds(3))]
 #[require(ShrinkAtEndOfRound(0.3))]
 pub enum CollectibleType {
     CoinType,
 }
 
+/// Card of the gadget that spawned the coin.
+#[derive(Component, Debug, Clone, Copy, Reflect)]
+pub struct CoinSource(pub ShopCardType);
+
 #[derive(Component, Debug, Clone, Reflect, Hash, PartialEq, Eq)]
 #[component(on_add=Coin::on_coin_added)]
 pub struct Coin {
@@ -227,11 +554,12 @@ impl CollectibleType {
 
 #[derive(Component, Debug, Reflect)]
 #[require(RigidBody::Dynamic)]
-#[require(Restitution::new(0.99))]
+#[require(Restitution::new(PlayerBall::RESTITUTION))]
 #[require(Collider::circle(25.0))]
 #[require(Name::new("player_ball"))]
 #[require(DestroyOnStandingStill::new(1.0, Duration::from_secs_f32(5.0)))]
 #[require(DrawTrajectory::new(1.0))]
+#[require(Combo)]
 #[require(GravityScale)]
 #[require(CollisionLayers::new(GameLayer::PlayerBallLayer,[
 GameLayer::DefaultLayer,
@@ -241,15 +569,6 @@ GameLayer::GadgetsLayer]))
 #[component(on_add=PlayerBall::on_add)]
 pub struct PlayerBall;
 
-#[derive(Component, Debug, Reflect)]
-#[require(RigidBody::Dynamic)]
-#[require(Restitution::new(0.99))]
-#[require(Collider::circle(25.0))]
-#[require(Name::new("player_ball_preview"))]
-#[require(DrawTrajectory::new(3.0))]
-#[require(DestroyOnStandingStill::new(1.0, Duration::from_secs_f32(5.0)))]
-pub struct FakePlayerBall;
-
 #[derive(Component, Debug, Reflect)]
 pub struct DrawTrajectory {
     pub duration: Duration,
@@ -264,6 +583,19 @@ impl DrawTrajectory {
 }
 
 impl PlayerBall {
+    pub const RESTITUTION: f32 = 0.99;
+    /// the ball is spawned at half scale
+    pub const RADIUS: f32 = 12.5;
+    pub const MAX_SPEED: f32 = 1000.0;
+
+    pub fn bundle(translation: Vec3, velocity: Vec2) -> impl Bundle {
+        (
+            PlayerBall,
+            Transform::from_translation(translation).with_scale(Vec3::splat(0.5)),
+            LinearVelocity(velocity),
+        )
+    }
+
     fn on_add(mut world: DeferredWorld, context: HookContext) {
         let game_resource = world.get_resource::<GameResources>().unwrap();
         let image = game_resource.ball_image.clone();
@@ -294,18 +626,24 @@ GameLayer::DefaultLayer
 #[component(on_add=Block::on_add)]
 pub struct Block {
     pub size: Vec2,
+    pub visual: GadgetType,
 }
 
 impl Block {
     pub fn new(size: Vec2) -> Self {
-        Self { size }
+        Self::with_visual(size, GadgetType::SquareBlockType)
+    }
+
+    pub fn with_visual(size: Vec2, visual: GadgetType) -> Self {
+        Self { size, visual }
     }
 
     fn on_add(mut world: DeferredWorld, context: HookContext) {
         let block = world.get::<Block>(context.entity).unwrap();
         let size = block.size;
+        let visual = block.visual;
         let game_resource = world.get_resource::<GameResources>().unwrap();
-        let image = game_resource.gadget_images[&GadgetType::SquareBlockType].clone();
+        let image = game_resource.gadget_images[&visual].clone();
         let slice_border = 30.0;
         let scale_mode = SpriteImageMode::Sliced(TextureSlicer {
             border: BorderRect::all(slice_border),
@@ -329,6 +667,61 @@ impl Block {
     }
 }
 
+#[derive(Component, Debug, Reflect)]
+#[require(Name::new("triangle_block"))]
+#[require(RigidBody::Static)]
+#[require(Restitution::new(0.7))]
+#[require(Collider)]
+#[require(Visibility)]
+#[require(Transform)]
+#[require(PointsOnHit::new(1))]
+#[require(Gadget::new(5))]
+#[require(CollisionEventsEnabled)]
+#[require(CollisionLayers::new(GameLayer::GadgetsLayer, [
+GameLayer::PlayerBallLayer,
+GameLayer::GadgetsLayer,
+GameLayer::DefaultLayer
+]))]
+#[require(CanBeRotated)]
+#[component(on_add=TriangleBlock::on_add)]
+pub struct TriangleBlock {
+    /// Length of the two sides enclosing the right angle.
+    pub size: f32,
+}
+
+impl Default for TriangleBlock {
+    fn default() -> Self {
+        Self { size: 70.0 }
+    }
+}
+
+impl TriangleBlock {
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        let half_size = world.get::<TriangleBlock>(context.entity).unwrap().size * 0.5;
+        let game_resource = world.get_resource::<GameResources>().unwrap();
+        let image = game_resource.gadget_images[&GadgetType::TriangleBlockType].clone();
+
+        world.commands().spawn((
+            ChildOf(context.entity),
+            SpriteVisualOf(context.entity),
+            Sprite {
+                image,
+                custom_size: Some(Vec2::splat(half_size * 2.0)),
+                ..default()
+            },
+        ));
+        // the right angle sits in the bottom left corner of the sprite
+        world
+            .commands()
+            .entity(context.entity)
+            .insert(Collider::triangle(
+                Vec2::new(-half_size, -half_size),
+                Vec2::new(half_size, -half_size),
+                Vec2::new(-half_size, half_size),
+            ));
+    }
+}
+
 #[derive(Component, Debug, Reflect)]
 #[component(on_add=PointsOnHit::on_add)]
 pub struct PointsOnHit {
@@ -374,6 +767,35 @@ pub struct WideBlock;
 #[require(Gadget::new(5))]
 pub struct SquareBlock;
 
+#[derive(Component, Debug, Reflect)]
+#[require(Name::new("high_friction_block"))]
+#[require(Block::with_visual(Vec2::new(120.0, 40.0), GadgetType::HighFrictionBlockType))]
+#[require(Friction = Friction::new(1.0).with_combine_rule(CoefficientCombine::Max))]
+#[require(Restitution = Restitution::new(0.05).with_combine_rule(CoefficientCombine::Min))]
+#[require(Gadget::new(3))]
+#[component(on_add=HighFrictionBlock::on_add)]
+pub struct HighFrictionBlock {
+    /// Fraction of the ball's velocity that is removed on contact.
+    pub velocity_drain: f32,
+}
+
+impl Default for HighFrictionBlock {
+    fn default() -> Self {
+        Self {
+            velocity_drain: 0.5,
+        }
+    }
+}
+
+impl HighFrictionBlock {
+    fn on_add(mut world: DeferredWorld, context: HookContext) {
+        world
+            .commands()
+            .entity(context.entity)
+            .insert(observers![on_hit_slow_down]);
+    }
+}
+
 #[derive(Component, Debug, Reflect, Deref, DerefMut)]
 pub struct ShrinkAtEndOfRound(pub f32);
 
@@ -1,3 +1,4 @@
+pub mod balance;
 pub mod components;
 pub mod resources;
 pub mod systems;
@@ -1,7 +1,10 @@
 use crate::cards::components::*;
+use crate::gadgets::balance::{BalanceConfig, CardDefinition, RarityWeight};
 use crate::gadgets::components::*;
 use bevy::prelude::*;
 use bevy_rand::prelude::{Entropy, WyRand};
+use rand::distr::weighted::WeightedIndex;
+use rand::distr::Distribution;
 use rand::seq::IteratorRandom;
 use std::collections::HashMap;
 use rand::Rng;
@@ -16,53 +19,97 @@ pub struct GameResources {
     pub reverse_gravity_image: Handle<Image>,
 
     pub balls_per_level: usize,
+    pub extra_balls: usize,
     pub gadgets: HashMap<ShopCardType, Gadget>,
     pub gadget_points: HashMap<ShopCardType, PointsOnHit>,
     pub activation_time: HashMap<ShopCardType, ActivationTime>,
+    pub balance_config: Handle<BalanceConfig>,
 
-    shop_items_per_level: Vec<Vec<ShopCardType>>,
+    pub card_removal_price: usize,
+    card_upgrade_price: usize,
+    reroll_price: usize,
+    reroll_price_increase: usize,
+
+    pub rare_pity: usize,
+
+    card_definitions: HashMap<ShopCardType, CardDefinition>,
+    rarity_weights: HashMap<CardRarity, RarityWeight>,
 }
 
 impl GameResources {
     pub fn setup(&mut self, asset_server: &AssetServer) {
-        self.play_area = Vec2::new(450.0, 250.0);
+        self.load_images(|path| asset_server.load(path));
+        self.balance_config = asset_server.load("config/game.balance.ron");
+    }
+
+    /// Without rendering only the balance config is loaded, the sprites get empty images.
+    pub fn setup_headless(&mut self, asset_server: &AssetServer) {
+        self.use_placeholder_images();
+        self.balance_config = asset_server.load("config/game.balance.ron");
+    }
+
+    pub fn use_placeholder_images(&mut self) {
+        self.load_images(|_| Handle::default());
+    }
 
+    fn load_images(&mut self, mut load: impl FnMut(&'static str) -> Handle<Image>) {
         self.gadget_images.insert(
             GadgetType::BumperType,
-            asset_server.load("sprites/bumper_points.png"),
+            load("sprites/bumper_points.png"),
         );
         self.gadget_images.insert(
             GadgetType::SquareBlockType,
-            asset_server.load("sprites/block_square.png"),
+            load("sprites/block_square.png"),
         );
         self.gadget_images.insert(
             GadgetType::WideBlockType,
-            asset_server.load("sprites/block_square.png"),
+            load("sprites/block_square.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::TriangleBlockType,
+            load("sprites/block_triangle.png"),
         );
 
         self.gadget_images.insert(
             GadgetType::CoinBumperType,
-            asset_server.load("sprites/bumper_coins.png"),
+            load("sprites/bumper_coins.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::HighFrictionBlockType,
+            load("sprites/block_wide.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::MultiBallType,
+            load("sprites/additional_ball.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::ReactivatorType,
+            load("sprites/ball_1.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::FlipperType,
+            load("sprites/flipper.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::PortalType,
+            load("sprites/portal.png"),
+        );
+        self.gadget_images.insert(
+            GadgetType::MagnetType,
+            load("sprites/bumper_coins.png"),
         );
         self.gadget_images.insert(
             GadgetType::BallCannonType,
-            asset_server.load("sprites/cannon.png"),
+            load("sprites/cannon.png"),
         );
 
         self.collectibles_images
-            .insert(CollectibleType::CoinType, asset_server.load("sprites/coin.png"));
+            .insert(CollectibleType::CoinType, load("sprites/coin.png"));
         
 
-        self.ball_image = asset_server.load("sprites/ball_1.png");
-        self.additional_ball_image = asset_server.load("sprites/additional_ball.png");
-        self.reverse_gravity_image = asset_server.load("sprites/gravity_reverse.png");
-
-
-        self.fill_gadgets();
-        self.fill_points_for_card();
-        self.fill_activation_time_for_card();
-        self.fill_shop_items();
-        self.balls_per_level = 3;
+        self.ball_image = load("sprites/ball_1.png");
+        self.additional_ball_image = load("sprites/additional_ball.png");
+        self.reverse_gravity_image = load("sprites/gravity_reverse.png");
     }
 
     pub fn get_random_position_in_play_area(&self, rng:&mut  Entropy<WyRand>) -> Vec2 {
@@ -71,162 +118,177 @@ impl GameResources {
         Vec2::new(x, y)
     }
     pub fn card_title(&self, shop_card_type: &ShopCardType) -> String {
-        match shop_card_type {
-            ShopCardType::OneMoreBallCard => "+1 Ball".to_string(),
-            ShopCardType::MoreBallsCard => format!("+{} Ball", self.balls_per_level),
-            ShopCardType::SquareBlockCard => "Square Block".to_string(),
-            ShopCardType::WideBlockCard => "Wide Block".to_string(),
-            ShopCardType::BumperCard => "Bumper".to_string(),
-            ShopCardType::CoinBumperCard => "CoinBumper".to_string(),
-            ShopCardType::HighFrictionBlockCard => "High Friction Block".to_string(),
-            ShopCardType::MagnetCard => "Magnetise".to_string(),
-            ShopCardType::ReactivateLaserBridgeCard => "Reactivate".to_string(),
-            ShopCardType::GravityReverserCard => "Gravity Reverser".to_string(),
-            ShopCardType::MultiBallCard => "Multi Ball".to_string(),
-            ShopCardType::RecycleGadgetCard => "Recycle Gadget".to_string(),
-            ShopCardType::RearrangeGadgetCard => "Rearrange Gadget".to_string(),
+        self.format_card_text(shop_card_type, &self.card_definitions[shop_card_type].title)
+    }
+
+    /// Rebuilds all balance values from the loaded config, replacing the previous ones.
+    pub fn apply_balance_config(&mut self, config: &BalanceConfig) {
+        self.play_area = Vec2::new(config.play_area.0, config.play_area.1);
+        self.balls_per_level = config.balls_per_level;
+        self.extra_balls = config.extra_balls;
+        self.card_definitions = config.cards.clone();
+        self.rarity_weights = config.rarity_weights.clone();
+        self.rare_pity = config.rare_pity;
+        self.card_removal_price = config.card_removal_price;
+        self.card_upgrade_price = config.card_upgrade_price;
+        self.reroll_price = config.reroll_price;
+        self.reroll_price_increase = config.reroll_price_increase;
+
+        self.gadgets.clear();
+        self.gadget_points.clear();
+        self.activation_time.clear();
+        for (card, definition) in config.cards.iter() {
+            if let Some(activations) = definition.activations {
+                self.gadgets.insert(*card, Gadget::new(activations));
+            }
+            if let Some(points) = definition.points {
+                self.gadget_points.insert(*card, PointsOnHit::new(points));
+            }
+            if let Some(seconds) = definition.activation_time {
+                self.activation_time
+                    .insert(*card, ActivationTime::new(seconds));
+            }
         }
     }
 
-    pub fn fill_gadgets(&mut self) {
-        self.gadgets
-            .insert(ShopCardType::SquareBlockCard, Gadget::new(5));
-        self.gadgets
-            .insert(ShopCardType::WideBlockCard, Gadget::new(5));
-        self.gadgets
-            .insert(ShopCardType::BumperCard, Gadget::new(3));
-        self.gadgets
-            .insert(ShopCardType::CoinBumperCard, Gadget::new(1));
+    pub fn card_description(&self, shop_card_type: &ShopCardType, level: usize) -> String {
+        let mut text = self.card_definitions[shop_card_type].description.clone();
+        if let Some(points) = self.points_for_level(shop_card_type, level) {
+            text = text.replace("{points}", &points.amount.to_string());
+        }
+        if let Some(gadget) = self.gadget_for_level(shop_card_type, level) {
+            text = text.replace("{activations}", &gadget.activations_per_round.to_string());
+        }
+        self.format_card_text(shop_card_type, &text)
+    }
+
+    /// Every upgrade level adds one activation per round.
+    pub fn gadget_for_level(&self, shop_card_type: &ShopCardType, level: usize) -> Option<Gadget> {
         self.gadgets
-            .insert(ShopCardType::MultiBallCard, Gadget::new(1));
+            .get(shop_card_type)
+            .map(|gadget| Gadget::new(gadget.activations_per_round + level))
     }
 
-    pub fn fill_points_for_card(&mut self) {
-        self.gadget_points
-            .insert(ShopCardType::SquareBlockCard, PointsOnHit::new(1));
-        self.gadget_points
-            .insert(ShopCardType::WideBlockCard, PointsOnHit::new(1));
+    /// Every upgrade level adds one point per hit.
+    pub fn points_for_level(
+        &self,
+        shop_card_type: &ShopCardType,
+        level: usize,
+    ) -> Option<PointsOnHit> {
         self.gadget_points
-            .insert(ShopCardType::BumperCard, PointsOnHit::new(3));
-    }
-
-    pub fn fill_activation_time_for_card(&mut self) {
-        self.activation_time
-            .insert(ShopCardType::MagnetCard, ActivationTime::new(5.0));
-    }
-
-    pub fn card_description(&self, shop_card_type: &ShopCardType) -> String {
-        match shop_card_type {
-            ShopCardType::OneMoreBallCard => "Adds 1 Ball".to_string(),
-            ShopCardType::MoreBallsCard => format!("Adds {} Balls", self.balls_per_level),
-            ShopCardType::SquareBlockCard => format!(
-                "{}x{} Points",
-                self.gadget_points.get(shop_card_type).unwrap().amount,
-                self.gadgets
-                    .get(shop_card_type)
-                    .unwrap()
-                    .activations_per_round
-            ),
-            ShopCardType::WideBlockCard => format!(
-                "{}x{} Points",
-                self.gadget_points.get(shop_card_type).unwrap().amount,
-                self.gadgets
-                    .get(shop_card_type)
-                    .unwrap()
-                    .activations_per_round
-            ),
-            ShopCardType::BumperCard => format!(
-                "{}x{} Points",
-                self.gadget_points.get(shop_card_type).unwrap().amount,
-                self.gadgets
-                    .get(shop_card_type)
-                    .unwrap()
-                    .activations_per_round
-            ),
-            ShopCardType::CoinBumperCard => "Spawn Coins".to_string(),
-            ShopCardType::HighFrictionBlockCard => "Slows down ball".to_string(),
-            ShopCardType::MagnetCard => format!(
-                "Attracts coins for {:.1}s",
-                self.activation_time
-                    .get(shop_card_type)
-                    .unwrap()
-                    .time
-                    .as_secs_f32()
-            ),
-            ShopCardType::ReactivateLaserBridgeCard => "Reactivate Gadgets".to_string(),
-            ShopCardType::GravityReverserCard => "Reverse Gravity in field".to_string(),
-            ShopCardType::MultiBallCard => "Duplicate Ball".to_string(),
-            ShopCardType::RecycleGadgetCard => "Recycle a Gadget for Coins".to_string(),
-            ShopCardType::RearrangeGadgetCard => "Move an already placed Gadget".to_string(),
-        }
+            .get(shop_card_type)
+            .map(|points| PointsOnHit::new(points.amount + level))
+    }
+
+    /// Only gadgets with activations or points get better with an upgrade.
+    pub fn is_upgradable(&self, shop_card_type: &ShopCardType) -> bool {
+        shop_card_type.get_gadget_type().is_some()
+            && (self.gadgets.contains_key(shop_card_type)
+                || self.gadget_points.contains_key(shop_card_type))
     }
 
+    /// Price of upgrading a card that is at `level` now.
+    pub fn get_upgrade_price(&self, level: usize) -> usize {
+        self.card_upgrade_price * (level + 1)
+    }
 
+    fn format_card_text(&self, shop_card_type: &ShopCardType, text: &str) -> String {
+        let mut text = text.replace("{extra_balls}", &self.extra_balls.to_string());
+        if let Some(points) = self.gadget_points.get(shop_card_type) {
+            text = text.replace("{points}", &points.amount.to_string());
+        }
+        if let Some(gadget) = self.gadgets.get(shop_card_type) {
+            text = text.replace("{activations}", &gadget.activations_per_round.to_string());
+        }
+        if let Some(activation_time) = self.activation_time.get(shop_card_type) {
+            text = text.replace(
+                "{activation_time}",
+                &format!("{:.1}", activation_time.time.as_secs_f32()),
+            );
+        }
+        text
+    }
+
+    pub fn card_rarity(&self, shop_card_type: &ShopCardType) -> Option<CardRarity> {
+        self.card_definitions
+            .get(shop_card_type)
+            .and_then(|definition| definition.rarity)
+    }
 
+    /// The free balls plus 3 cards, each slot rolls a rarity with the weights of `level` first.
+    /// Once `shops_without_rare` reaches the pity the first slot is a rare card, if one is left.
     pub fn get_shop_cards_for_level(
         &self,
         level: usize,
+        shops_without_rare: usize,
+        banned_cards: &[ShopCardType],
         rng: &mut Entropy<WyRand>,
     ) -> Vec<ShopCardType> {
         let mut cards = vec![ShopCardType::MoreBallsCard];
 
-        let safe_level = level.min(self.shop_items_per_level.len() - 1);
+        // sorted, the iteration order of the definitions is not the same on every run
+        let mut pool: Vec<ShopCardType> = self
+            .card_definitions
+            .keys()
+            .filter(|card| self.card_rarity(card).is_some() && !banned_cards.contains(card))
+            .copied()
+            .collect();
+        pool.sort();
 
-        for card in self.shop_items_per_level[safe_level]
+        // with every rare card banned the pity can't be paid out, the slot rolls like the others
+        let has_rare = pool
             .iter()
-            .choose_multiple(rng, 3)
-        {
-            cards.push(*card);
+            .any(|card| self.card_rarity(card) == Some(CardRarity::Rare));
+        for slot in 0..3 {
+            let rarity = if slot == 0 && shops_without_rare >= self.rare_pity && has_rare {
+                Some(CardRarity::Rare)
+            } else {
+                self.roll_rarity(level, &pool, rng)
+            };
+            let Some(rarity) = rarity else {
+                break;
+            };
+            let Some(index) = pool
+                .iter()
+                .enumerate()
+                .filter(|(_, card)| self.card_rarity(card) == Some(rarity))
+                .map(|(index, _)| index)
+                .choose(rng)
+            else {
+                continue;
+            };
+            cards.push(pool.remove(index));
         }
 
         cards
     }
-    pub fn fill_shop_items(&mut self) {
-        // Level 1
-        self.shop_items_per_level.push(vec![
-            ShopCardType::OneMoreBallCard,
-            ShopCardType::BumperCard,
-            ShopCardType::CoinBumperCard,
-        ]);
-        // Level 2
-        self.shop_items_per_level.push(vec![
-            ShopCardType::OneMoreBallCard,
-            ShopCardType::BumperCard,
-            ShopCardType::CoinBumperCard,
-            ShopCardType::WideBlockCard,
-        ]);
-        // Level 3
-        self.shop_items_per_level.push(vec![
-            ShopCardType::OneMoreBallCard,
-            ShopCardType::BumperCard,
-            ShopCardType::CoinBumperCard,
-            ShopCardType::WideBlockCard,
-            ShopCardType::GravityReverserCard,
-        ]);
-        self.shop_items_per_level.push(vec![
-            ShopCardType::OneMoreBallCard,
-            ShopCardType::BumperCard,
-            ShopCardType::CoinBumperCard,
-            ShopCardType::WideBlockCard,
-            ShopCardType::GravityReverserCard,
-        ]);
+
+    /// Rarities without a card left in the pool are skipped.
+    fn roll_rarity(
+        &self,
+        level: usize,
+        pool: &[ShopCardType],
+        rng: &mut Entropy<WyRand>,
+    ) -> Option<CardRarity> {
+        let weights = CardRarity::ALL.map(|rarity| {
+            let is_available = pool
+                .iter()
+                .any(|card| self.card_rarity(card) == Some(rarity));
+            match self.rarity_weights.get(&rarity) {
+                Some(weight) if is_available => weight.at_level(level),
+                _ => 0.0,
+            }
+        });
+        let distribution = WeightedIndex::new(weights).ok()?;
+        Some(CardRarity::ALL[distribution.sample(rng)])
     }
 
     pub fn get_price_per_card(&self, shop_card_type: &ShopCardType) -> usize {
-        match shop_card_type {
-            ShopCardType::OneMoreBallCard => 3,
-            ShopCardType::MoreBallsCard => 0,
-            ShopCardType::SquareBlockCard => 1,
-            ShopCardType::WideBlockCard => 1,
-            ShopCardType::BumperCard => 5,
-            ShopCardType::CoinBumperCard => 15,
-            ShopCardType::HighFrictionBlockCard => 4,
-            ShopCardType::MagnetCard => 9,
-            ShopCardType::ReactivateLaserBridgeCard => 25,
-            ShopCardType::GravityReverserCard => 25,
-            ShopCardType::MultiBallCard => 35,
-            ShopCardType::RecycleGadgetCard => 15,
-            ShopCardType::RearrangeGadgetCard => 8,
-        }
+        self.card_definitions[shop_card_type].price
+    }
+
+    /// Price of the next reroll after `rerolls` rerolls in the same shop visit.
+    pub fn get_reroll_price(&self, rerolls: usize) -> usize {
+        self.reroll_price + self.reroll_price_increase * rerolls
     }
 }
@@ -1,8 +1,12 @@
+use crate::gadgets::balance::BalanceConfig;
 use crate::gadgets::components::*;
+use crate::gadgets::resources::GameResources;
 use crate::game_ui::components::FloatingScore;
 use crate::gameplay::components::*;
+use crate::gameplay::resources::{FlipperInput, RoundBreakdown};
 use crate::general::components::SpriteVisual;
 use avian2d::prelude::*;
+use bevy::asset::AssetLoadFailedEvent;
 use bevy::color::palettes::tailwind;
 use bevy::prelude::*;
 use bevy_bundled_observers::observers;
@@ -12,6 +16,7 @@ use bevy_rand::prelude::WyRand;
 use bevy_simple_subsecond_system::hot;
 use bevy_vector_shapes::prelude::*;
 use rand::Rng;
+use std::f32::consts::{PI, TAU};
 use std::time::Duration;
 use crate::gameplay::events::RequestToPlaceCoins;
 
@@ -19,17 +24,25 @@ use crate::gameplay::events::RequestToPlaceCoins;
 pub fn on_coins_spawn_from_bumper(
     trigger: Trigger<OnCollisionStart>,
     mut commands: Commands,
-    mut q_gadget: Query<(&mut Gadget, &CoinBumperGadget), Without<GadgetDeactivated>>,
+    mut q_gadget: Query<
+        (&mut Gadget, &CoinBumperGadget, Option<&SpawnedFromCard>),
+        Without<GadgetDeactivated>,
+    >,
     ball_query: Query<Entity, With<PlayerBall>>,
 ) {
     if ball_query.get(trigger.collider).is_err() {
         return;
     }
-    let Ok((mut gadget, coin_bumper)) = q_gadget.get_mut(trigger.target()) else {
+    let Ok((mut gadget, coin_bumper, spawned_from_card)) = q_gadget.get_mut(trigger.target())
+    else {
         return;
     };
 
-    commands.trigger(RequestToPlaceCoins::new(coin_bumper.coins_to_spawn));
+    let mut request = RequestToPlaceCoins::new(coin_bumper.coins_to_spawn);
+    if let Some(spawned_from_card) = spawned_from_card {
+        request = request.with_source(spawned_from_card.0);
+    }
+    commands.trigger(request);
     gadget.activations_left -= 1;
 
     if gadget.activations_left == 0 {
@@ -42,28 +55,401 @@ pub fn on_coins_spawn_from_bumper(
 
 }
 
+pub fn on_magnet_hit(
+    trigger: Trigger<OnCollisionStart>,
+    mut commands: Commands,
+    mut q_magnet: Query<(&mut Gadget, &mut MagnetGadget), Without<GadgetDeactivated>>,
+    ball_query: Query<Entity, With<PlayerBall>>,
+) {
+    if ball_query.get(trigger.collider).is_err() {
+        return;
+    }
+    let Ok((mut gadget, mut magnet)) = q_magnet.get_mut(trigger.target()) else {
+        return;
+    };
+
+    magnet.time_left = magnet.active_time;
+    gadget.activations_left = gadget.activations_left.saturating_sub(1);
+
+    if gadget.activations_left == 0 {
+        commands
+            .entity(trigger.target())
+            .try_insert(GadgetDeactivated);
+    }
+}
+
+/// Coins within the radius of an active magnet are pulled towards the closest ball.
+pub fn magnet_system(
+    time: Res<Time>,
+    mut q_magnet: Query<(&mut MagnetGadget, &Transform, &SpriteVisual)>,
+    q_ball: Query<&Transform, With<PlayerBall>>,
+    mut q_coin: Query<&mut Transform, (With<Coin>, Without<PlayerBall>, Without<MagnetGadget>)>,
+    mut q_visual: Query<
+        &mut Transform,
+        (Without<Coin>, Without<PlayerBall>, Without<MagnetGadget>),
+    >,
+) {
+    for (mut magnet, magnet_transform, sprite_visual) in q_magnet.iter_mut() {
+        if !magnet.is_active() {
+            continue;
+        }
+        magnet.time_left = magnet.time_left.saturating_sub(time.delta());
+
+        if let Ok(mut visual_transform) = q_visual.get_mut(**sprite_visual) {
+            if magnet.is_active() {
+                visual_transform.rotate_z(TAU * time.delta_secs());
+            } else {
+                visual_transform.rotation = Quat::IDENTITY;
+            }
+        }
+
+        let magnet_position = magnet_transform.translation.xy();
+        for mut coin_transform in q_coin.iter_mut() {
+            let coin_position = coin_transform.translation.xy();
+            if coin_position.distance(magnet_position) > magnet.radius {
+                continue;
+            }
+            let Some(to_ball) = q_ball
+                .iter()
+                .map(|ball_transform| ball_transform.translation.xy() - coin_position)
+                .min_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))
+            else {
+                continue;
+            };
+            let distance = to_ball.length();
+            if distance <= f32::EPSILON {
+                continue;
+            }
+            let step = (magnet.pull_speed * time.delta_secs()).min(distance);
+            coin_transform.translation += (to_ball / distance * step).extend(0.0);
+        }
+    }
+}
+
+pub fn on_hit_slow_down(
+    trigger: Trigger<OnCollisionStart>,
+    q_block: Query<&HighFrictionBlock>,
+    mut q_ball: Query<&mut LinearVelocity, With<PlayerBall>>,
+) {
+    let Ok(block) = q_block.get(trigger.target()) else {
+        return;
+    };
+    let Ok(mut velocity) = q_ball.get_mut(trigger.collider) else {
+        return;
+    };
+    velocity.0 *= 1.0 - block.velocity_drain;
+}
+
+pub fn on_hit_split_ball(
+    trigger: Trigger<OnCollisionStart>,
+    mut commands: Commands,
+    mut q_gadget: Query<(&mut Gadget, &MultiBallGadget), Without<GadgetDeactivated>>,
+    q_ball: Query<(&Transform, &LinearVelocity, &GravityScale, Option<&SplitFrom>), With<PlayerBall>>,
+) {
+    let Ok((ball_transform, velocity, gravity_scale, split_from)) = q_ball.get(trigger.collider)
+    else {
+        return;
+    };
+    if split_from.is_some_and(|split_from| split_from.0 == trigger.target()) {
+        return;
+    }
+    let Ok((mut gadget, multi_ball)) = q_gadget.get_mut(trigger.target()) else {
+        return;
+    };
+
+    for index in 0..multi_ball.extra_balls {
+        let fanned_velocity = Vec2::from_angle(multi_ball.fan_angle(index)).rotate(velocity.0);
+        commands.spawn((
+            PlayerBall::bundle(ball_transform.translation, fanned_velocity),
+            GravityScale(gravity_scale.0),
+            SplitFrom(trigger.target()),
+        ));
+    }
+
+    gadget.activations_left = gadget.activations_left.saturating_sub(1);
+    if gadget.activations_left == 0 {
+        commands
+            .entity(trigger.target())
+            .try_insert(GadgetDeactivated);
+    }
+}
+
+pub fn on_ball_entering_reactivator(
+    trigger: Trigger<OnCollisionStart>,
+    mut commands: Commands,
+    mut q_reactivator: Query<
+        (&Transform, &ReactivatorField, &mut Gadget),
+        Without<GadgetDeactivated>,
+    >,
+    mut q_gadgets: Query<(Entity, &Transform, &mut Gadget), Without<ReactivatorField>>,
+    ball_query: Query<Entity, With<PlayerBall>>,
+    game_resources: Res<GameResources>,
+) {
+    if ball_query.get(trigger.collider).is_err() {
+        return;
+    }
+    let Ok((reactivator_transform, reactivator, mut reactivator_gadget)) =
+        q_reactivator.get_mut(trigger.target())
+    else {
+        return;
+    };
+
+    let center = reactivator_transform.translation;
+    for (entity, transform, mut gadget) in q_gadgets.iter_mut() {
+        if transform.translation.distance(center) > reactivator.radius {
+            continue;
+        }
+        gadget.activations_left = gadget.activations_per_round;
+        commands.entity(entity).try_remove::<GadgetDeactivated>();
+    }
+
+    reactivator_gadget.activations_left = reactivator_gadget.activations_left.saturating_sub(1);
+    if reactivator_gadget.activations_left == 0 {
+        commands
+            .entity(trigger.target())
+            .try_insert(GadgetDeactivated);
+    }
+
+    let pulse_transform = Transform::from_translation(center).with_scale(Vec3::splat(0.1));
+    let easing = pulse_transform.ease_to_fn(
+        |start| Transform {
+            scale: Vec3::ONE,
+            ..*start
+        },
+        EaseFunction::QuadraticOut,
+        EasingType::Once {
+            duration: Duration::from_secs_f32(0.4),
+        },
+    );
+    commands.spawn((
+        pulse_transform,
+        Sprite {
+            image: game_resources.gadget_images[&GadgetType::ReactivatorType].clone(),
+            color: ReactivatorField::TINT.with_alpha(0.3),
+            custom_size: Some(Vec2::splat(reactivator.radius * 2.0)),
+            ..default()
+        },
+        easing,
+        observers![on_finish_easing_destroy],
+    ));
+}
+
+/// Picks up changes to the balance config while the game is running and applies them to the
+/// gadgets already on the field. A config that fails to load or is incomplete is skipped, the last
+/// good one stays in use.
+pub fn reload_balance_config_system(
+    mut asset_events: EventReader<AssetEvent<BalanceConfig>>,
+    mut failed_events: EventReader<AssetLoadFailedEvent<BalanceConfig>>,
+    balance_configs: Res<Assets<BalanceConfig>>,
+    mut game_resources: ResMut<GameResources>,
+    mut q_gadget: Query<(
+        &SpawnedFromCard,
+        Option<&UpgradeLevel>,
+        Option<&mut Gadget>,
+        Option<&mut PointsOnHit>,
+    )>,
+) {
+    for event in failed_events.read() {
+        error!("{}, keeping the previous balance config", event.error);
+    }
+    for event in asset_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        if *id != game_resources.balance_config.id() {
+            continue;
+        }
+        let Some(config) = balance_configs.get(*id) else {
+            continue;
+        };
+        if let Err(error) = config.validate() {
+            error!("{error}, keeping the previous balance config");
+            continue;
+        }
+        game_resources.apply_balance_config(config);
+        info!("balance config reloaded");
+
+        for (spawned_from_card, upgrade_level, gadget, points) in q_gadget.iter_mut() {
+            // upgrades bought in the shop stay on top of the new values
+            let level = upgrade_level.map_or(0, |upgrade_level| upgrade_level.0);
+            if let (Some(mut gadget), Some(balanced_gadget)) = (
+                gadget,
+                game_resources.gadget_for_level(&spawned_from_card.0, level),
+            ) {
+                let used_activations = gadget
+                    .activations_per_round
+                    .saturating_sub(gadget.activations_left);
+                gadget.activations_per_round = balanced_gadget.activations_per_round;
+                gadget.activations_left = balanced_gadget
+                    .activations_per_round
+                    .saturating_sub(used_activations);
+            }
+            if let (Some(mut points), Some(balanced_points)) = (
+                points,
+                game_resources.points_for_level(&spawned_from_card.0, level),
+            ) {
+                points.amount = balanced_points.amount;
+            }
+        }
+    }
+}
+
+pub fn store_flipper_rest_angle(mut q_flipper: Query<(&mut Flipper, &Transform)>) {
+    for (mut flipper, transform) in q_flipper.iter_mut() {
+        flipper.rest_angle = transform.rotation.to_euler(EulerRot::XYZ).2;
+    }
+}
+
+pub fn flipper_input_system(
+    key_input: Res<ButtonInput<KeyCode>>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut flipper_input: ResMut<FlipperInput>,
+) {
+    flipper_input.set_if_neq(FlipperInput {
+        is_flipping: key_input.pressed(KeyCode::Space) || mouse_input.pressed(MouseButton::Right),
+    });
+}
+
+pub fn flipper_control_system(
+    flipper_input: Res<FlipperInput>,
+    mut q_flipper: Query<(&Flipper, &Rotation, &mut AngularVelocity), Without<Preview>>,
+) {
+    let is_flipping = flipper_input.is_flipping;
+    for (flipper, rotation, mut angular_velocity) in q_flipper.iter_mut() {
+        let direction = flipper.flip_direction();
+        let swing =
+            ((rotation.as_radians() - flipper.rest_angle) * direction + PI).rem_euclid(TAU) - PI;
+        let (target, stiffness) = if is_flipping {
+            (flipper.max_angle, flipper.flip_stiffness)
+        } else {
+            (0.0, flipper.return_stiffness)
+        };
+        // spring towards the target, the limits are never overshot as long as the stiffness stays
+        // below the physics tick rate
+        let speed = ((target - swing) * stiffness)
+            .clamp(-flipper.max_angular_speed, flipper.max_angular_speed);
+        angular_velocity.0 = speed * direction;
+    }
+}
+
+pub fn reset_flippers(
+    mut q_flipper: Query<(
+        &Flipper,
+        &mut Transform,
+        &mut Rotation,
+        &mut AngularVelocity,
+    )>,
+    mut flipper_input: ResMut<FlipperInput>,
+) {
+    flipper_input.set_if_neq(FlipperInput::default());
+    for (flipper, mut transform, mut rotation, mut angular_velocity) in q_flipper.iter_mut() {
+        transform.rotation = Quat::from_rotation_z(flipper.rest_angle);
+        *rotation = Rotation::radians(flipper.rest_angle);
+        angular_velocity.0 = 0.0;
+    }
+}
+
+pub fn on_ball_entering_portal(
+    trigger: Trigger<OnCollisionStart>,
+    mut commands: Commands,
+    q_portal: Query<(&Portal, &Transform), Without<PlayerBall>>,
+    mut q_ball: Query<
+        (&mut Transform, &mut Position, &mut LinearVelocity),
+        (With<PlayerBall>, Without<PortalCooldown>),
+    >,
+) {
+    let Ok((portal, _)) = q_portal.get(trigger.target()) else {
+        return;
+    };
+    let Some(Ok((_, exit_transform))) = portal.linked_portal.map(|exit| q_portal.get(exit)) else {
+        return;
+    };
+    let Ok((mut ball_transform, mut position, mut velocity)) = q_ball.get_mut(trigger.collider)
+    else {
+        return;
+    };
+
+    let exit_direction = (exit_transform.rotation * Vec3::Y).truncate();
+    velocity.0 = exit_direction * velocity.0.length();
+    position.0 = exit_transform.translation.xy();
+    ball_transform.translation.x = exit_transform.translation.x;
+    ball_transform.translation.y = exit_transform.translation.y;
+
+    commands
+        .entity(trigger.collider)
+        .try_insert(PortalCooldown::default());
+}
+
+pub fn portal_cooldown_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut q_cooldown: Query<(Entity, &mut PortalCooldown)>,
+) {
+    for (entity, mut cooldown) in q_cooldown.iter_mut() {
+        cooldown.0.tick(time.delta());
+        if cooldown.0.finished() {
+            commands.entity(entity).remove::<PortalCooldown>();
+        }
+    }
+}
+
+pub fn draw_portal_link_system(
+    mut painter: ShapePainter,
+    q_portal: Query<(&Portal, &Transform, Has<Preview>)>,
+) {
+    painter.thickness = 3.0;
+    painter.color = tailwind::PURPLE_400.with_alpha(0.6).into();
+    for (portal, transform, is_preview) in q_portal.iter() {
+        if !is_preview {
+            continue;
+        }
+        let Some(Ok((_, linked_transform, _))) =
+            portal.linked_portal.map(|linked| q_portal.get(linked))
+        else {
+            continue;
+        };
+        painter.line(transform.translation, linked_transform.translation);
+    }
+}
+
 pub fn on_hit_gain_points(
     trigger: Trigger<OnCollisionStart>,
     mut commands: Commands,
-    mut hit_query: Query<(Entity, &Transform, &PointsOnHit, &mut Gadget)>,
-    q_ball: Query<Entity, With<PlayerBall>>,
+    mut hit_query: Query<(
+        Entity,
+        &Transform,
+        &PointsOnHit,
+        &mut Gadget,
+        Option<&SpawnedFromCard>,
+    )>,
+    mut q_ball: Query<&mut Combo, With<PlayerBall>>,
     mut player: Single<&mut Player>,
+    mut round_breakdown: ResMut<RoundBreakdown>,
 ) {
-    if q_ball.get(trigger.collider).is_err() {
+    let Ok(mut combo) = q_ball.get_mut(trigger.collider) else {
         return;
-    }
-    let Ok((entity, transform, points_on_hit, mut gadget)) = hit_query.get_mut(trigger.target())
+    };
+    let Ok((entity, transform, points_on_hit, mut gadget, spawned_from_card)) =
+        hit_query.get_mut(trigger.target())
     else {
         return;
     };
 
     if gadget.activations_left > 0 {
         gadget.activations_left -= 1;
-        player.points += points_on_hit.amount;
-        player.points_this_round += points_on_hit.amount;
+        let multiplier = combo.hit();
+        let points = points_on_hit.amount * multiplier;
+        player.points += points;
+        player.points_this_round += points;
+        round_breakdown.add_hit(
+            entity,
+            spawned_from_card.map(|spawned_from_card| spawned_from_card.0),
+            points_on_hit.amount,
+            multiplier,
+        );
         commands.spawn((
             Transform::from_translation(transform.translation),
-            FloatingScore(points_on_hit.amount),
+            FloatingScore::new(points_on_hit.amount, multiplier),
         ));
 
         if gadget.activations_left == 0 {
@@ -72,6 +458,15 @@ pub fn on_hit_gain_points(
     }
 }
 
+/// Ends the combo of balls that went too long without scoring.
+pub fn combo_timeout_system(time: Res<Time>, mut q_combo: Query<&mut Combo>) {
+    for mut combo in q_combo.iter_mut() {
+        if combo.window.tick(time.delta()).just_finished() {
+            combo.hits = 0;
+        }
+    }
+}
+
 pub fn on_hit_bounce(
     trigger: Trigger<OnCollisionStart>,
     mut commands: Commands,
@@ -39,22 +39,52 @@ pub struct UiClickOnCannonText;
 #[derive(Component)]
 pub struct UiCardPriceText;
 
+#[derive(Component)]
+pub struct UiRerollPriceText;
+
+#[derive(Component)]
+pub struct UiDeckViewer;
+
+/// Hidden until the timer runs out, the round summary shows its rows one after another.
+#[derive(Component)]
+pub struct UiRevealDelay(pub Timer);
+
 #[derive(Component)]
 pub struct UiMainMenu;
 
+/// Seed typed into the main menu, empty for a random seed.
+#[derive(Component, Default)]
+pub struct UiSeedInput(pub String);
+
 #[derive(Component)]
 pub struct Forbidden;
 
 #[derive(Component)]
 pub struct UiShopElement;
 
+/// Overlay to pick the card a shop service is applied to.
+#[derive(Component)]
+pub struct UiShopServicePicker;
+
 #[derive(Component)]
 #[component(on_add=FloatingScore::on_add)]
-pub struct FloatingScore(pub usize);
+pub struct FloatingScore {
+    pub points: usize,
+    pub multiplier: usize,
+}
 
 impl FloatingScore {
+    pub fn new(points: usize, multiplier: usize) -> Self {
+        Self { points, multiplier }
+    }
+
     fn on_add(mut world: DeferredWorld, context: HookContext) {
-        let score = world.get::<FloatingScore>(context.entity).unwrap().0;
+        let floating_score = world.get::<FloatingScore>(context.entity).unwrap();
+        let text = if floating_score.multiplier > 1 {
+            format!("{} x{}", floating_score.points, floating_score.multiplier)
+        } else {
+            format!("{}", floating_score.points)
+        };
         let transform = world.get::<Transform>(context.entity).unwrap();
         let easing = transform.ease_to_fn(
             |start| Transform {
@@ -69,7 +99,7 @@ impl FloatingScore {
         );
 
         world.commands().entity(context.entity).insert((
-            Text2d(format!("{}", score)),
+            Text2d(text),
             TextColor(tailwind::GRAY_800.into())
                 .ease_to(
                     TextColor(tailwind::GRAY_100.into()),
@@ -12,13 +12,25 @@ impl Plugin for GameUiPlugin {
         app.add_systems(OnEnter(AppState::InGame), setup_ui);
         app.add_systems(Update, update_ui);
         app.add_systems(Update, button_system);
+        app.add_systems(
+            Update,
+            deck_viewer_key_system.run_if(in_state(AppState::InGame)),
+        );
 
         app.add_systems(OnEnter(MenuState::MainMenu), show_menu_ui);
         app.add_systems(OnEnter(MenuState::CreditsMenu), show_credits);
+        app.add_systems(Update, seed_input_system.run_if(in_state(MenuState::MainMenu)));
 
         app.add_systems(OnEnter(LevelState::GameOver), spawn_level_over_ui);
         app.add_systems(OnEnter(LevelState::WidgetSelection), show_widget_selection);
+        app.add_systems(OnEnter(LevelState::EndOfRound), show_round_summary);
+        app.add_systems(
+            Update,
+            reveal_round_summary_system.run_if(in_state(LevelState::EndOfRound)),
+        );
         app.add_systems(OnEnter(LevelState::Shop), show_shop_ui);
+        app.add_systems(OnEnter(LevelState::RecycleGadget), show_recycle_gadget_ui);
+        app.add_systems(OnEnter(LevelState::RearrangeGadget), show_rearrange_gadget_ui);
         app.add_systems(OnEnter(LevelState::ShootBall), on_entering_shoot_ball_state);
         app.add_systems(OnExit(LevelState::ShootBall), on_exit_shoot_ball_state);
 
@@ -26,6 +38,9 @@ impl Plugin for GameUiPlugin {
         app.add_systems(Update, update_shop_ui.run_if(in_state(LevelState::Shop)));
 
         app.add_observer(widget_selection_ui_despawn);
+        app.add_observer(on_leave_shop_request);
+        app.add_observer(on_toggle_deck_viewer);
+        app.add_observer(on_shop_rerolled);
 
 
     }
@@ -1,16 +1,25 @@
-use crate::cards::components::{OnClickOnShopCard, ShopCard};
+use crate::cards::components::{OnClickOnShopCard, ShopCard, ShopCardType};
+use crate::gadgets::components::PlayerPlacedGadget;
 use crate::gadgets::resources::GameResources;
 use crate::game_ui::components::*;
 use crate::gameplay::components::*;
-use crate::gameplay::events::OnGadgetCardSelected;
+use crate::gameplay::events::{
+    OnGadgetCardSelected, OnShopRerolled, RequestShopService, RequestToContinueRound,
+    RequestToLeaveShop, RequestToRerollShop, ShopService, ToggleDeckViewer,
+};
 use crate::gameplay::game_states::{AppState, LevelState, MenuState};
+use crate::gameplay::resources::{RoundBreakdown, RunModifiers, RunRng, RunSeed, ShopRerolls};
+use crate::replay::resources::{RecordReplayAction, ReplayAction, ReplayLog, ReplayPlayback};
+use crate::save::resources::{DailyScores, PendingRunRestore, SaveGame};
 use bevy::color::palettes::tailwind;
+use bevy::input::keyboard::{Key, KeyboardInput};
 use bevy::prelude::*;
 use bevy_bundled_observers::bevy_ecs::spawn::SpawnIter;
 use bevy_bundled_observers::observers;
 use bevy_rand::global::GlobalEntropy;
 use bevy_rand::prelude::WyRand;
 use bevy_simple_subsecond_system::hot;
+use chrono::Local;
 use rand::prelude::*;
 
 #[derive(Component)]
@@ -23,6 +32,9 @@ pub struct MainUi;
 pub fn setup_ui(
     mut commands: Commands,
     player: Single<&Player>,
+    run_seed: Option<Res<RunSeed>>,
+    run_modifiers: Res<RunModifiers>,
+    game_resources: Res<GameResources>,
     destroy_query: Query<Entity, With<MainUi>>,
 ) {
     info!("Setup main ui UI");
@@ -230,11 +242,70 @@ pub fn setup_ui(
                         TextColor(tailwind::RED_300.into()),
                     )
                 ]
-            )
+            ),
+            (
+                Text::new(format!(
+                    "Seed: {}",
+                    run_seed.map(|seed| seed.text.clone()).unwrap_or_default()
+                )),
+                TextFont {
+                    font_size: font_size * 0.6,
+                    ..default()
+                },
+                TextColor(tailwind::GRAY_500.into()),
+            ),
+            (
+                Text::new(run_modifiers_text(&run_modifiers, &game_resources)),
+                TextFont {
+                    font_size: font_size * 0.6,
+                    ..default()
+                },
+                TextColor(tailwind::GRAY_500.into()),
+            ),
+            (
+                Button,
+                Node {
+                    width: Val::Px(110.0),
+                    height: Val::Px(36.0),
+                    margin: UiRect::top(Val::Px(6.0)),
+                    border: UiRect::all(Val::Px(3.0)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                BorderColor(Color::BLACK),
+                BorderRadius::MAX,
+                BackgroundColor(NORMAL_BUTTON),
+                children![(
+                    Text::new("Deck (D)"),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(Color::srgb(0.9, 0.9, 0.9)),
+                )],
+                observers![|_: Trigger<Pointer<Click>>, mut commands: Commands| {
+                    commands.trigger(ToggleDeckViewer);
+                }]
+            ),
         ],
     ));
 }
 
+fn run_modifiers_text(run_modifiers: &RunModifiers, game_resources: &GameResources) -> String {
+    let Some(date) = &run_modifiers.daily else {
+        return String::new();
+    };
+    let mut text = format!("Daily {}", date);
+    if let Some(balls_per_level) = run_modifiers.balls_per_level {
+        text.push_str(&format!("\n{} Balls per Level", balls_per_level));
+    }
+    for banned_card in run_modifiers.banned_cards.iter() {
+        text.push_str(&format!("\nNo {}", game_resources.card_title(banned_card)));
+    }
+    text
+}
+
 #[hot]
 pub fn update_ui(
     player: Single<&Player>,
@@ -361,27 +432,40 @@ pub fn show_widget_selection(
     mut commands: Commands,
     previous_setup: Query<Entity, With<DestroyShowWidgetSelectionUi>>,
     mut player: Single<&mut Player>,
-    mut rng: GlobalEntropy<WyRand>,
+    mut run_rng: ResMut<RunRng>,
+    q_placed: Query<(), With<PlayerPlacedGadget>>,
 ) {
     for entity in previous_setup.iter() {
         commands.entity(entity).try_despawn();
     }
     let z_position = 50.0;
 
-    while player.current_hand.len() < 3 {
-        let next_card = player.next_card(&mut rng);
-        player.current_hand.push(next_card);
-    }
+    player.fill_hand(&mut run_rng.deck);
 
     let mut x_position = -300.0;
 
     for card in player.current_hand.iter() {
-        commands.spawn((
-            ShopCard::new(card.clone()),
-            DestroyShowWidgetSelectionUi,
-            Transform::from_xyz(x_position, 0.0, z_position),
-            OnGadgetCardSelected::new(card.clone()),
-        ));
+        let card_entity = commands
+            .spawn((
+                ShopCard::new(card.clone()).with_level(player.card_level(card)),
+                DestroyShowWidgetSelectionUi,
+                Transform::from_xyz(x_position, 0.0, z_position),
+                OnGadgetCardSelected::new(card.clone()),
+            ))
+            .id();
+        if !player.is_card_playable(card, !q_placed.is_empty()) {
+            commands.entity(card_entity).insert(Forbidden);
+            commands.spawn((
+                ChildOf(card_entity),
+                Transform::from_xyz(0.0, -125.0, 0.0),
+                Text2d("No gadget placed".to_string()),
+                TextColor(tailwind::RED_300.into()),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+            ));
+        }
         x_position += 300.0;
     }
 
@@ -433,9 +517,10 @@ pub fn show_widget_selection(
         "You can use the 'R' Key top rotate some gadgets",
         "The shop will always offer you a few free balls",
         "You can always destroy the balls by clicking on the cannon",
+        "Press Space or the right mouse button to use your flippers",
     ];
 
-    let tip = tips.choose(&mut rng).unwrap();
+    let tip = tips.choose(&mut run_rng.tips).unwrap();
     commands.spawn((
         ChildOf(root_entity),
         Node {
@@ -457,18 +542,19 @@ pub fn show_widget_selection(
 #[hot(rerun_on_hot_patch = true)]
 pub fn show_shop_ui(
     mut commands: Commands,
-    player: Single<&Player>,
+    mut player: Single<&mut Player>,
     game_resources: Res<GameResources>,
-    mut rng: GlobalEntropy<WyRand>,
+    mut run_rng: ResMut<RunRng>,
+    run_modifiers: Res<RunModifiers>,
     reload_query: Query<Entity, With<UiShopElement>>,
 ) {
     for entity in reload_query.iter() {
         commands.entity(entity).try_despawn();
     }
 
-    let cards = game_resources.get_shop_cards_for_level(player.current_level, &mut rng);
+    let cards =
+        player.roll_shop_offers(&game_resources, &run_modifiers.banned_cards, &mut run_rng.shop);
 
-    let mut position_x = -300.0;
     let position_z = 50.0;
 
     commands.spawn((
@@ -477,44 +563,32 @@ pub fn show_shop_ui(
         Sprite::from_color(Color::BLACK.with_alpha(0.95), Vec2::new(5000.0, 5000.0)),
     ));
 
-    for card in cards.iter() {
-        let price = game_resources.get_price_per_card(card);
-        let price_color = if price > player.coins {
-            Color::from(tailwind::RED_700)
-        } else {
-            Color::from(tailwind::GRAY_50)
-        };
-        let price_text = if price == 0 {
-            "Free!".to_string()
-        } else {
-            format!("Cost: {} Coins", price)
-        };
-        let card_entity = commands
-            .spawn((
-                UiShopElement,
-                Transform::from_xyz(position_x, 0.0, position_z),
-                Visibility::Visible,
-                ShopCard::new(card.clone()),
-                OnClickOnShopCard,
-                children![(
-                    UiCardPriceText,
-                    Sprite::from_color(Color::from(tailwind::GRAY_800), Vec2::new(135.0, 25.0)),
-                    Transform::from_xyz(0.0, -130.0, 0.0),
-                    Text2d(price_text),
-                    TextColor(price_color),
-                    TextFont {
-                        font_size: 14.0,
-                        ..default()
-                    },
-                )],
-            ))
-            .id();
-
-        if player.coins < price {
-            commands.entity(card_entity).insert(Forbidden);
-        }
-        position_x += 200.0
+    for (index, card) in cards.into_iter().enumerate() {
+        spawn_shop_offer(&mut commands, &player, &game_resources, card, index);
     }
+
+    commands.spawn((
+        UiShopElement,
+        Transform::from_xyz(0.0, -185.0, position_z),
+        Sprite::from_color(Color::from(tailwind::GRAY_800), Vec2::new(220.0, 40.0)),
+        children![(
+            UiRerollPriceText,
+            Transform::from_xyz(0.0, 0.0, 1.0),
+            Text2d::default(),
+            TextFont {
+                font_size: 16.0,
+                ..default()
+            },
+        )],
+        observers![|_: Trigger<Pointer<Click>>,
+                    mut commands: Commands,
+                    playback: Option<Res<ReplayPlayback>>| {
+            if playback.is_none() {
+                commands.trigger(RequestToRerollShop);
+            }
+        }],
+    ));
+
     let font_color = Color::from(tailwind::RED_300);
 
     commands.spawn((
@@ -541,6 +615,42 @@ pub fn show_shop_ui(
         ],
     ));
 
+    let services = [
+        (
+            ShopService::RemoveCard,
+            format!("Remove a Card ({} Coins)", game_resources.card_removal_price),
+            -300.0,
+        ),
+        (
+            ShopService::UpgradeCard,
+            "Upgrade a Card".to_string(),
+            300.0,
+        ),
+    ];
+    for (service, text, x_position) in services {
+        commands.spawn((
+            UiShopElement,
+            Transform::from_xyz(x_position, 140.0, position_z),
+            Sprite::from_color(Color::from(tailwind::GRAY_800), Vec2::new(220.0, 40.0)),
+            children![(
+                Transform::from_xyz(0.0, 0.0, 1.0),
+                Text2d(text),
+                TextFont {
+                    font_size: 16.0,
+                    ..default()
+                },
+                Pickable::IGNORE,
+            )],
+            observers![move |_: Trigger<Pointer<Click>>,
+                             mut commands: Commands,
+                             playback: Option<Res<ReplayPlayback>>| {
+                if playback.is_none() {
+                    commands.run_system_cached_with(show_shop_service_picker, service);
+                }
+            }],
+        ));
+    }
+
     commands.spawn((
         UiShopElement,
         Transform::from_xyz(0.0, 140.0, position_z),
@@ -593,25 +703,234 @@ pub fn show_shop_ui(
             )],
             observers![|_: Trigger<Pointer<Click>>,
                         mut commands: Commands,
-                        ui_level_over_query: Query<Entity, With<UiShopElement>>,
-                        mut next_state: ResMut<NextState<LevelState>>| {
-                for entity in ui_level_over_query.iter() {
-                    commands.entity(entity).despawn();
+                        playback: Option<Res<ReplayPlayback>>| {
+                if playback.is_none() {
+                    commands.trigger(RequestToLeaveShop);
                 }
-                next_state.set(LevelState::WidgetSelection);
             }]
         )],
     ));
 }
 
+/// Offers are lined up in the order the shop picked them, the free ones come first.
+fn spawn_shop_offer(
+    commands: &mut Commands,
+    player: &Player,
+    game_resources: &GameResources,
+    card: ShopCardType,
+    index: usize,
+) {
+    let price = game_resources.get_price_per_card(&card);
+    let price_color = if price > player.coins {
+        Color::from(tailwind::RED_700)
+    } else {
+        Color::from(tailwind::GRAY_50)
+    };
+    let card_entity = commands
+        .spawn((
+            UiShopElement,
+            Transform::from_xyz(-300.0 + index as f32 * 200.0, 0.0, 50.0),
+            Visibility::Visible,
+            ShopCard::new(card).with_level(player.card_level(&card)),
+            OnClickOnShopCard,
+            children![(
+                UiCardPriceText,
+                Sprite::from_color(Color::from(tailwind::GRAY_800), Vec2::new(135.0, 25.0)),
+                Transform::from_xyz(0.0, -130.0, 0.0),
+                Text2d(card_price_text(price)),
+                TextColor(price_color),
+                TextFont {
+                    font_size: 14.0,
+                    ..default()
+                },
+            )],
+        ))
+        .id();
+
+    if player.coins < price {
+        commands.entity(card_entity).insert(Forbidden);
+    }
+}
+
+/// Replaces the offers that cost something, bought ones included, the free ones stay.
+pub fn on_shop_rerolled(
+    _: Trigger<OnShopRerolled>,
+    mut commands: Commands,
+    player: Single<&Player>,
+    game_resources: Res<GameResources>,
+    mut run_rng: ResMut<RunRng>,
+    run_modifiers: Res<RunModifiers>,
+    q_offer: Query<(Entity, &ShopCard), With<OnClickOnShopCard>>,
+) {
+    for (entity, shop_card) in q_offer.iter() {
+        if game_resources.get_price_per_card(&shop_card.card_type) > 0 {
+            commands.entity(entity).try_despawn();
+        }
+    }
+
+    let cards =
+        player.reroll_shop_offers(&game_resources, &run_modifiers.banned_cards, &mut run_rng.shop);
+    for (index, card) in cards.into_iter().enumerate() {
+        if game_resources.get_price_per_card(&card) > 0 {
+            spawn_shop_offer(&mut commands, &player, &game_resources, card, index);
+        }
+    }
+}
+
+/// Lists the cards of the deck the service can be applied to, clicking the background cancels.
+fn show_shop_service_picker(
+    In(service): In<ShopService>,
+    mut commands: Commands,
+    player: Single<&Player>,
+    game_resources: Res<GameResources>,
+) {
+    let (title, cards): (&str, Vec<ShopCardType>) = match service {
+        ShopService::RemoveCard => (
+            "Pick a Card to remove",
+            Player::count_cards(player.full_deck())
+                .into_iter()
+                .map(|(card, _)| card)
+                .collect(),
+        ),
+        ShopService::UpgradeCard => (
+            "Pick a Card to upgrade",
+            Player::count_cards(player.full_deck())
+                .into_iter()
+                .map(|(card, _)| card)
+                .filter(|card| game_resources.is_upgradable(card))
+                .collect(),
+        ),
+    };
+
+    commands.spawn((
+        UiShopElement,
+        UiShopServicePicker,
+        Transform::from_xyz(0.0, 0.0, 60.0),
+        Sprite::from_color(Color::BLACK.with_alpha(0.9), Vec2::new(5000.0, 5000.0)),
+        observers![close_shop_service_picker],
+    ));
+    commands.spawn((
+        UiShopElement,
+        UiShopServicePicker,
+        Transform::from_xyz(0.0, 200.0, 70.0),
+        Text2d(title.to_string()),
+        TextFont {
+            font_size: 24.0,
+            ..default()
+        },
+        TextColor(tailwind::YELLOW_500.into()),
+    ));
+
+    let columns = 6;
+    let spacing = 110.0;
+    let row_length = columns.min(cards.len()) as f32;
+    for (index, card) in cards.into_iter().enumerate() {
+        let level = player.card_level(&card);
+        let (price, is_available) = match service {
+            ShopService::RemoveCard => (game_resources.card_removal_price, player.can_remove_card()),
+            ShopService::UpgradeCard => (
+                game_resources.get_upgrade_price(level),
+                player.can_upgrade_card(&card),
+            ),
+        };
+        let price_text = if is_available {
+            card_price_text(price)
+        } else {
+            "Not available".to_string()
+        };
+        let price_color = if price > player.coins || !is_available {
+            Color::from(tailwind::RED_700)
+        } else {
+            Color::from(tailwind::GRAY_50)
+        };
+
+        let column = (index % columns) as f32;
+        let x_position = (column - (row_length - 1.0) * 0.5) * spacing;
+        let y_position = 60.0 - (index / columns) as f32 * 170.0;
+        let card_entity = commands
+            .spawn((
+                UiShopElement,
+                UiShopServicePicker,
+                ShopCard::new(card).with_level(level),
+                Transform::from_xyz(x_position, y_position, 70.0).with_scale(Vec3::splat(0.6)),
+                children![(
+                    Transform::from_xyz(0.0, -125.0, 0.0),
+                    Text2d(price_text),
+                    TextColor(price_color),
+                    TextFont {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                )],
+                observers![move |trigger: Trigger<Pointer<Click>>,
+                                 mut commands: Commands,
+                                 q_forbidden: Query<(), With<Forbidden>>,
+                                 picker_query: Query<Entity, With<UiShopServicePicker>>| {
+                    if q_forbidden.contains(trigger.target()) {
+                        return;
+                    }
+                    commands.trigger(RequestShopService { service, card });
+                    for entity in picker_query.iter() {
+                        commands.entity(entity).try_despawn();
+                    }
+                }],
+            ))
+            .id();
+        if price > player.coins || !is_available {
+            commands.entity(card_entity).insert(Forbidden);
+        }
+    }
+}
+
+fn close_shop_service_picker(
+    _: Trigger<Pointer<Click>>,
+    mut commands: Commands,
+    picker_query: Query<Entity, With<UiShopServicePicker>>,
+) {
+    for entity in picker_query.iter() {
+        commands.entity(entity).try_despawn();
+    }
+}
+
+pub fn on_leave_shop_request(
+    _: Trigger<RequestToLeaveShop>,
+    mut commands: Commands,
+    ui_shop_query: Query<Entity, With<UiShopElement>>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    for entity in ui_shop_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    commands.trigger(RecordReplayAction(ReplayAction::LeaveShop));
+    next_state.set(LevelState::WidgetSelection);
+}
+
 pub fn update_shop_ui(
     mut commands: Commands,
-    mut ui_shop_price_text: Query<(&mut TextColor, &ChildOf), With<UiCardPriceText>>,
+    mut ui_shop_price_text: Query<(&mut Text2d, &mut TextColor, &ChildOf), With<UiCardPriceText>>,
+    mut ui_reroll_price_text: Query<
+        (&mut Text2d, &mut TextColor),
+        (With<UiRerollPriceText>, Without<UiCardPriceText>),
+    >,
     shop_card_query: Query<&ShopCard>,
     player: Single<&Player>,
     game_resources: Res<GameResources>,
+    shop_rerolls: Res<ShopRerolls>,
 ) {
-    for (mut text_color, parent) in ui_shop_price_text.iter_mut() {
+    let reroll_price = game_resources.get_reroll_price(shop_rerolls.0);
+    for (mut text, mut text_color) in ui_reroll_price_text.iter_mut() {
+        let reroll_text = format!("Reroll ({} Coins)", reroll_price);
+        if text.0 != reroll_text {
+            text.0 = reroll_text;
+        }
+        text_color.0 = if reroll_price > player.coins {
+            tailwind::RED_700.into()
+        } else {
+            tailwind::GRAY_50.into()
+        };
+    }
+
+    for (mut text, mut text_color, parent) in ui_shop_price_text.iter_mut() {
         let card = shop_card_query.get(parent.0).unwrap();
         let price = game_resources.get_price_per_card(&card.card_type);
         let price_color = if price > player.coins {
@@ -622,9 +941,252 @@ pub fn update_shop_ui(
 
         text_color.0 = price_color;
 
+        // prices can change while the shop is open when the balance config is reloaded
+        let price_text = card_price_text(price);
+        if text.0 != price_text {
+            text.0 = price_text;
+        }
+
         if price > player.coins {
             commands.entity(parent.0).try_insert(Forbidden);
+        } else {
+            commands.entity(parent.0).try_remove::<Forbidden>();
+        }
+    }
+}
+
+fn card_price_text(price: usize) -> String {
+    if price == 0 {
+        "Free!".to_string()
+    } else {
+        format!("Cost: {} Coins", price)
+    }
+}
+
+pub fn show_round_summary(
+    mut commands: Commands,
+    round_breakdown: Res<RoundBreakdown>,
+    game_resources: Res<GameResources>,
+) {
+    let header_row = ["Gadget", "Hits", "Points", "Combo Bonus"].map(String::from);
+    let gadget_rows = round_breakdown.sorted_gadgets().into_iter().map(|gadget_score| {
+        let title = gadget_score
+            .card
+            .map_or("Gadget".to_string(), |card| game_resources.card_title(&card));
+        [
+            title,
+            gadget_score.hits.to_string(),
+            gadget_score.points.to_string(),
+            gadget_score.combo_bonus.to_string(),
+        ]
+    });
+    let rows: Vec<[String; 4]> = std::iter::once(header_row).chain(gadget_rows).collect();
+    let row_count = rows.len();
+    let totals_text = if row_count > 1 {
+        format!(
+            "Coins collected: {}   Combo bonus: {}   Round points: {}",
+            round_breakdown.coins,
+            round_breakdown.combo_bonus(),
+            round_breakdown.points()
+        )
+    } else {
+        format!(
+            "No gadget scored this round   Coins collected: {}",
+            round_breakdown.coins
+        )
+    };
+
+    commands.spawn((
+        Name::new("round_summary"),
+        StateScoped(LevelState::EndOfRound),
+        Pickable::IGNORE,
+        Node {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        children![(
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                row_gap: Val::Px(8.0),
+                padding: UiRect::all(Val::Px(20.0)),
+                ..default()
+            },
+            BorderRadius::all(Val::Px(10.0)),
+            BackgroundColor(Color::BLACK.with_alpha(0.85)),
+            children![
+                (
+                    Text::new("Round Summary"),
+                    TextFont {
+                        font_size: 30.0,
+                        ..default()
+                    },
+                    TextColor(tailwind::YELLOW_500.into()),
+                ),
+                (
+                    Node {
+                        flex_direction: FlexDirection::Column,
+                        row_gap: Val::Px(4.0),
+                        ..default()
+                    },
+                    Children::spawn(SpawnIter(rows.into_iter().enumerate().map(
+                        |(index, cells)| summary_row(index, cells)
+                    ))),
+                ),
+                (
+                    UiRevealDelay(summary_reveal_timer(row_count)),
+                    Visibility::Hidden,
+                    Text::new(totals_text),
+                    TextFont {
+                        font_size: 18.0,
+                        ..default()
+                    },
+                    TextColor(tailwind::YELLOW_400.into()),
+                ),
+                (
+                    button_bundle("Continue"),
+                    observers![|_: Trigger<Pointer<Click>>,
+                                mut commands: Commands,
+                                playback: Option<Res<ReplayPlayback>>| {
+                        if playback.is_none() {
+                            commands.trigger(RequestToContinueRound);
+                        }
+                    }]
+                ),
+            ],
+        )],
+    ));
+}
+
+fn summary_reveal_timer(index: usize) -> Timer {
+    Timer::from_seconds(index as f32 * 0.2, TimerMode::Once)
+}
+
+/// The first row is the header.
+fn summary_row(index: usize, cells: [String; 4]) -> impl Bundle {
+    let text_color: Color = if index == 0 {
+        tailwind::GRAY_400.into()
+    } else {
+        tailwind::GRAY_100.into()
+    };
+    (
+        UiRevealDelay(summary_reveal_timer(index)),
+        Visibility::Hidden,
+        Node {
+            flex_direction: FlexDirection::Row,
+            column_gap: Val::Px(10.0),
+            ..default()
+        },
+        Children::spawn(SpawnIter(cells.into_iter().enumerate().map(
+            move |(column, text)| {
+                (
+                    Text(text),
+                    TextFont {
+                        font_size: 16.0,
+                        ..default()
+                    },
+                    TextColor(text_color),
+                    Node {
+                        width: Val::Px(if column == 0 { 200.0 } else { 110.0 }),
+                        ..default()
+                    },
+                )
+            },
+        ))),
+    )
+}
+
+pub fn reveal_round_summary_system(
+    time: Res<Time>,
+    mut q_reveal: Query<(&mut UiRevealDelay, &mut Visibility)>,
+) {
+    for (mut reveal_delay, mut visibility) in q_reveal.iter_mut() {
+        if reveal_delay.0.tick(time.delta()).just_finished() {
+            *visibility = Visibility::Inherited;
+        }
+    }
+}
+
+pub fn deck_viewer_key_system(mut commands: Commands, key_input: Res<ButtonInput<KeyCode>>) {
+    if key_input.just_pressed(KeyCode::KeyD) {
+        commands.trigger(ToggleDeckViewer);
+    }
+}
+
+pub fn on_toggle_deck_viewer(
+    _: Trigger<ToggleDeckViewer>,
+    mut commands: Commands,
+    player: Single<&Player>,
+    deck_viewer_query: Query<Entity, With<UiDeckViewer>>,
+) {
+    if !deck_viewer_query.is_empty() {
+        for entity in deck_viewer_query.iter() {
+            commands.entity(entity).try_despawn();
         }
+        return;
+    }
+
+    commands.spawn((
+        UiDeckViewer,
+        StateScoped(AppState::InGame),
+        Transform::from_xyz(0.0, 0.0, 100.0),
+        Sprite::from_color(Color::BLACK.with_alpha(0.9), Vec2::new(5000.0, 5000.0)),
+        observers![|_: Trigger<Pointer<Click>>, mut commands: Commands| {
+            commands.trigger(ToggleDeckViewer);
+        }],
+    ));
+
+    let piles = [
+        ("Draw Pile", Player::count_cards(&player.widget_deck), 230.0),
+        ("Discard Pile", Player::count_cards(&player.discard_pile), 0.0),
+        ("Full Deck", Player::count_cards(player.full_deck()), -230.0),
+    ];
+    for (title, counts, y_position) in piles {
+        spawn_deck_viewer_row(&mut commands, &player, title, counts, y_position);
+    }
+}
+
+fn spawn_deck_viewer_row(
+    commands: &mut Commands,
+    player: &Player,
+    title: &str,
+    counts: Vec<(ShopCardType, usize)>,
+    y_position: f32,
+) {
+    let card_count: usize = counts.iter().map(|(_, count)| count).sum();
+    commands.spawn((
+        UiDeckViewer,
+        StateScoped(AppState::InGame),
+        Transform::from_xyz(0.0, y_position + 85.0, 110.0),
+        Text2d(format!("{} ({})", title, card_count)),
+        TextFont {
+            font_size: 20.0,
+            ..default()
+        },
+        TextColor(tailwind::YELLOW_500.into()),
+    ));
+
+    let spacing = 100.0;
+    let mut x_position = -(counts.len().saturating_sub(1) as f32) * spacing * 0.5;
+    for (card, count) in counts {
+        commands.spawn((
+            UiDeckViewer,
+            StateScoped(AppState::InGame),
+            ShopCard::new(card).with_level(player.card_level(&card)),
+            Transform::from_xyz(x_position, y_position, 110.0).with_scale(Vec3::splat(0.6)),
+            children![(
+                Transform::from_xyz(0.0, -125.0, 0.0),
+                Text2d(format!("x{}", count)),
+                TextFont {
+                    font_size: 24.0,
+                    ..default()
+                },
+            )],
+        ));
+        x_position += spacing;
     }
 }
 
@@ -644,64 +1206,247 @@ pub fn on_exit_shoot_ball_state(
     commands.entity(entity).insert(Visibility::Hidden);
 }
 
+pub fn show_recycle_gadget_ui(mut commands: Commands) {
+    commands.spawn(gadget_action_hint(
+        LevelState::RecycleGadget,
+        "Click a placed Gadget to recycle it for Coins (Right Click to skip)",
+    ));
+}
+
+pub fn show_rearrange_gadget_ui(mut commands: Commands) {
+    commands.spawn(gadget_action_hint(
+        LevelState::RearrangeGadget,
+        "Click a placed Gadget to move it (Right Click to skip)",
+    ));
+}
+
+fn gadget_action_hint(state: LevelState, text: impl Into<String>) -> impl Bundle {
+    (
+        Name::new("gadget_action_hint"),
+        StateScoped(state),
+        Pickable::IGNORE,
+        Node {
+            width: Val::Percent(100.0),
+            top: Val::Px(60.0),
+            position_type: PositionType::Absolute,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        children![(
+            Text::new(text),
+            TextFont {
+                font_size: 20.0,
+                ..default()
+            },
+            TextColor(tailwind::YELLOW_400.into()),
+        )],
+    )
+}
+
 #[hot]
 pub fn show_menu_ui(mut commands: Commands, previous_query: Query<Entity, With<UiMainMenu>>) {
     for entity in previous_query.iter() {
         commands.entity(entity).despawn();
     }
+    let today = Local::now().date_naive().format("%Y-%m-%d").to_string();
+    let daily_best_text = match DailyScores::load().map(|scores| scores.best(&today)) {
+        Ok(Some(best)) => format!("Today's best: {}", best),
+        _ => "No daily run today yet".to_string(),
+    };
+    let root_entity = commands
+        .spawn((
+            UiMainMenu,
+            Pickable::IGNORE,
+            StateScoped(MenuState::MainMenu),
+            Node {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(20.0),
+                column_gap: Val::Px(20.0),
+                ..default()
+            },
+        ))
+        .id();
+
+    // the entries are spawned top to bottom, the optional ones only when there is something to
+    // load
+    commands.spawn((ChildOf(root_entity), header("Boingle"), TextShadow::default()));
     commands.spawn((
-        UiMainMenu,
-        Pickable::IGNORE,
-        StateScoped(MenuState::MainMenu),
+        ChildOf(root_entity),
         Node {
-            width: Val::Percent(100.0),
-            height: Val::Percent(100.0),
-            align_items: AlignItems::Center,
-            justify_content: JustifyContent::Center,
-            flex_direction: FlexDirection::Column,
-            row_gap: Val::Px(20.0),
-            column_gap: Val::Px(20.0),
+            width: Val::Px(300.0),
             ..default()
         },
-        children![
-            (header("Boingle"), TextShadow::default(),),
-            (
-                Node {
-                    width: Val::Px(300.0),
-                    ..default()
-                },
-                Text(
-                    "Build chaotic pinball contraptions \
-                where every ball sparks a chain reaction \
-                of bounces, points and coins!"
-                        .to_string()
-                ),
-                TextLayout::new_with_justify(JustifyText::Justified),
-                TextFont {
-                    font_size: 12.0,
-                    ..default()
-                },
-            ),
-            (
-                button_bundle("Start Game"),
-                observers![
-                    |_: Trigger<Pointer<Click>>, mut next_state: ResMut<NextState<AppState>>| {
+        Text(
+            "Build chaotic pinball contraptions \
+        where every ball sparks a chain reaction \
+        of bounces, points and coins!"
+                .to_string(),
+        ),
+        TextLayout::new_with_justify(JustifyText::Justified),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+    ));
+    commands.spawn((
+        ChildOf(root_entity),
+        UiSeedInput::default(),
+        Text::new(seed_input_label("")),
+        TextFont {
+            font_size: 16.0,
+            ..default()
+        },
+        TextColor(tailwind::GRAY_300.into()),
+    ));
+
+    if matches!(SaveGame::load(), Ok(Some(_))) {
+        commands.spawn((
+            ChildOf(root_entity),
+            button_bundle("Continue"),
+            observers![|_: Trigger<Pointer<Click>>,
+                        mut commands: Commands,
+                        mut rng: GlobalEntropy<WyRand>,
+                        mut next_state: ResMut<NextState<AppState>>| {
+                match SaveGame::load() {
+                    Ok(Some(save_game)) => {
+                        let run_seed = match &save_game.seed {
+                            Some(seed) => RunSeed::from_text(seed),
+                            None => RunSeed::random(&mut rng),
+                        };
+                        insert_run_seed(&mut commands, run_seed);
+                        // pick the streams up where the save left them instead of at the seed
+                        if let Some(run_rng) = &save_game.rng {
+                            commands.insert_resource(run_rng.clone());
+                        }
+                        commands.insert_resource(save_game.modifiers.clone());
+                        commands.insert_resource(PendingRunRestore(save_game));
                         next_state.set(AppState::InGame);
                     }
-                ]
-            ),
-            (
-                button_bundle("Credits"),
-                observers![
-                    |_: Trigger<Pointer<Click>>, mut next_state: ResMut<NextState<MenuState>>| {
-                        next_state.set(MenuState::CreditsMenu);
+                    Ok(None) => warn!("there is no saved run to continue"),
+                    Err(error) => warn!("{error}"),
+                }
+            }],
+        ));
+    }
+
+    commands.spawn((
+        ChildOf(root_entity),
+        button_bundle("Start Game"),
+        observers![|_: Trigger<Pointer<Click>>,
+                    mut commands: Commands,
+                    seed_input: Single<&UiSeedInput>,
+                    mut rng: GlobalEntropy<WyRand>,
+                    mut next_state: ResMut<NextState<AppState>>| {
+            let run_seed = if seed_input.0.is_empty() {
+                RunSeed::random(&mut rng)
+            } else {
+                RunSeed::from_text(&seed_input.0)
+            };
+            commands.insert_resource(RunModifiers::default());
+            insert_run_seed(&mut commands, run_seed);
+            next_state.set(AppState::InGame);
+        }],
+    ));
+    commands.spawn((
+        ChildOf(root_entity),
+        button_bundle("Daily"),
+        observers![|_: Trigger<Pointer<Click>>,
+                    mut commands: Commands,
+                    mut next_state: ResMut<NextState<AppState>>| {
+            let (run_seed, run_modifiers) = RunModifiers::daily(Local::now().date_naive());
+            commands.insert_resource(run_modifiers);
+            insert_run_seed(&mut commands, run_seed);
+            next_state.set(AppState::InGame);
+        }],
+    ));
+    commands.spawn((
+        ChildOf(root_entity),
+        Text::new(daily_best_text),
+        TextFont {
+            font_size: 12.0,
+            ..default()
+        },
+        TextColor(tailwind::GRAY_400.into()),
+    ));
+
+    if matches!(ReplayLog::load(), Ok(Some(_))) {
+        commands.spawn((
+            ChildOf(root_entity),
+            button_bundle("Replay Last Run"),
+            observers![|_: Trigger<Pointer<Click>>,
+                        mut commands: Commands,
+                        mut next_state: ResMut<NextState<AppState>>| {
+                match ReplayLog::load() {
+                    Ok(Some(replay_log)) => {
+                        insert_run_seed(&mut commands, RunSeed::from_text(&replay_log.seed));
+                        commands.insert_resource(replay_log.modifiers);
+                        commands.insert_resource(ReplayPlayback::new(replay_log.actions));
+                        next_state.set(AppState::InGame);
                     }
-                ]
-            ),
+                    Ok(None) => warn!("there is no recorded run to replay"),
+                    Err(error) => warn!("{error}"),
+                }
+            }],
+        ));
+    }
+
+    commands.spawn((
+        ChildOf(root_entity),
+        button_bundle("Credits"),
+        observers![
+            |_: Trigger<Pointer<Click>>, mut next_state: ResMut<NextState<MenuState>>| {
+                next_state.set(MenuState::CreditsMenu);
+            }
         ],
     ));
 }
 
+fn insert_run_seed(commands: &mut Commands, run_seed: RunSeed) {
+    info!("Starting run with seed {}", run_seed.text);
+    commands.insert_resource(RunRng::from_seed(&run_seed));
+    commands.insert_resource(run_seed);
+}
+
+fn seed_input_label(seed: &str) -> String {
+    if seed.is_empty() {
+        "Seed: random (type to enter one)".to_string()
+    } else {
+        format!("Seed: {}", seed)
+    }
+}
+
+pub fn seed_input_system(
+    mut keyboard_events: EventReader<KeyboardInput>,
+    seed_input: Single<(&mut UiSeedInput, &mut Text)>,
+) {
+    let (mut seed_input, mut text) = seed_input.into_inner();
+    for event in keyboard_events.read() {
+        if !event.state.is_pressed() {
+            continue;
+        }
+        match &event.logical_key {
+            Key::Backspace => {
+                seed_input.0.pop();
+            }
+            Key::Character(characters) => {
+                for character in characters.chars().filter(char::is_ascii_alphanumeric) {
+                    if seed_input.0.len() < RunSeed::MAX_LENGTH {
+                        seed_input.0.push(character.to_ascii_uppercase());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if seed_input.is_changed() {
+        text.0 = seed_input_label(&seed_input.0);
+    }
+}
+
 pub fn show_credits(mut commands: Commands) {
     commands.spawn((
         Pickable::IGNORE,
@@ -3,14 +3,18 @@ use crate::gadgets::components::*;
 use crate::gadgets::resources::GameResources;
 use crate::game_ui::components::UiClickOnCannonText;
 use crate::gameplay::game_states::LevelState;
+use crate::gameplay::resources::{PendingBallClear, RunModifiers};
+use crate::replay::resources::ReplayPlayback;
 use avian2d::prelude::*;
+use bevy::color::palettes::tailwind;
 use bevy::ecs::component::HookContext;
 use bevy::ecs::world::DeferredWorld;
 use bevy::prelude::*;
 use bevy_bundled_observers::observers;
 use bevy_rand::prelude::*;
 use rand::prelude::SliceRandom;
-use std::f32::consts::TAU;
+use std::f32::consts::{FRAC_PI_6, PI, TAU};
+use std::collections::BTreeMap;
 use std::time::Duration;
 
 #[derive(Component, Debug, Reflect, Default)]
@@ -28,11 +32,33 @@ pub struct Player {
     pub balls_per_level: usize,
     pub current_level: usize,
     pub point_for_next_level: usize,
+    /// upgrades bought in the shop, they apply to every copy of a card
+    pub card_levels: BTreeMap<ShopCardType, usize>,
+    /// shop offers in a row without a rare card, see [`GameResources::rare_pity`]
+    pub shops_without_rare: usize,
 }
 
 impl Player {
-    pub fn new(balls_per_level: usize, rng: &mut Entropy<WyRand>) -> Self {
-        let starter_deck = vec![
+    pub const MAX_CARD_LEVEL: usize = 3;
+    /// the shop does not take cards away below this
+    pub const MIN_DECK_SIZE: usize = 5;
+
+    /// The balls are set by [`Player::apply_modifiers`] once the balance config is loaded.
+    pub fn new(rng: &mut Entropy<WyRand>) -> Self {
+        let starter_deck = Player::default_starter_deck();
+        let mut widget_deck = starter_deck.clone();
+        widget_deck.shuffle(rng);
+
+        Self {
+            starter_deck,
+            widget_deck,
+            point_for_next_level: Player::points_for_level(0),
+            ..default()
+        }
+    }
+
+    pub fn default_starter_deck() -> Vec<ShopCardType> {
+        vec![
             ShopCardType::WideBlockCard,
             ShopCardType::WideBlockCard,
             ShopCardType::WideBlockCard,
@@ -44,18 +70,18 @@ impl Player {
             ShopCardType::BumperCard,
             ShopCardType::BumperCard,
             ShopCardType::CoinBumperCard,
-        ];
-        let mut widget_deck = starter_deck.clone();
-        widget_deck.shuffle(rng);
+        ]
+    }
 
-        Self {
-            balls_left: balls_per_level,
-            balls_per_level,
-            starter_deck,
-            widget_deck,
-            point_for_next_level: Player::points_for_level(0),
-            ..default()
-        }
+    /// Takes effect with the next [`Player::reset`].
+    pub fn apply_modifiers(&mut self, modifiers: &RunModifiers, game_resources: &GameResources) {
+        self.starter_deck = modifiers
+            .starter_deck
+            .clone()
+            .unwrap_or_else(Player::default_starter_deck);
+        self.balls_per_level = modifiers
+            .balls_per_level
+            .unwrap_or(game_resources.balls_per_level);
     }
 
     pub fn points_for_level(level: usize) -> usize {
@@ -69,6 +95,133 @@ impl Player {
         }
         self.widget_deck.pop().unwrap()
     }
+    /// Every card the player owns, wherever it is right now.
+    pub fn full_deck(&self) -> impl Iterator<Item = &ShopCardType> {
+        self.widget_deck
+            .iter()
+            .chain(self.discard_pile.iter())
+            .chain(self.current_hand.iter())
+    }
+
+    /// Number of cards per type in a fixed order, so a listed draw pile does not give away what
+    /// comes next.
+    pub fn count_cards<'a>(
+        cards: impl IntoIterator<Item = &'a ShopCardType>,
+    ) -> Vec<(ShopCardType, usize)> {
+        let mut counts = BTreeMap::new();
+        for card in cards {
+            *counts.entry(*card).or_insert(0) += 1;
+        }
+        counts.into_iter().collect()
+    }
+
+    /// Offers of a new shop visit for the current level, only these count towards the rare pity.
+    pub fn roll_shop_offers(
+        &mut self,
+        game_resources: &GameResources,
+        banned_cards: &[ShopCardType],
+        rng: &mut Entropy<WyRand>,
+    ) -> Vec<ShopCardType> {
+        let cards = game_resources.get_shop_cards_for_level(
+            self.current_level,
+            self.shops_without_rare,
+            banned_cards,
+            rng,
+        );
+        if cards
+            .iter()
+            .any(|card| game_resources.card_rarity(card) == Some(CardRarity::Rare))
+        {
+            self.shops_without_rare = 0;
+        } else {
+            self.shops_without_rare += 1;
+        }
+        cards
+    }
+
+    /// New offers for the shop that is open, rerolls leave the rare pity as it is so they can not
+    /// be used to force a rare card.
+    pub fn reroll_shop_offers(
+        &self,
+        game_resources: &GameResources,
+        banned_cards: &[ShopCardType],
+        rng: &mut Entropy<WyRand>,
+    ) -> Vec<ShopCardType> {
+        game_resources.get_shop_cards_for_level(
+            self.current_level,
+            self.shops_without_rare,
+            banned_cards,
+            rng,
+        )
+    }
+
+    pub fn card_level(&self, card: &ShopCardType) -> usize {
+        self.card_levels.get(card).copied().unwrap_or(0)
+    }
+
+    pub fn can_upgrade_card(&self, card: &ShopCardType) -> bool {
+        self.card_level(card) < Player::MAX_CARD_LEVEL && self.full_deck().any(|owned| owned == card)
+    }
+
+    pub fn upgrade_card(&mut self, card: ShopCardType) {
+        *self.card_levels.entry(card).or_insert(0) += 1;
+    }
+
+    pub fn can_remove_card(&self) -> bool {
+        self.full_deck().count() > Player::MIN_DECK_SIZE
+    }
+
+    /// Takes one copy of `card` out of the deck, the draw pile first so the current hand stays
+    /// as it is. Returns false if the player does not own the card.
+    pub fn remove_card(&mut self, card: ShopCardType) -> bool {
+        for pile in [
+            &mut self.widget_deck,
+            &mut self.discard_pile,
+            &mut self.current_hand,
+        ] {
+            if let Some(index) = pile.iter().position(|owned| *owned == card) {
+                pile.remove(index);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Recycle and Rearrange need a placed gadget. With nothing else in the hand they stay
+    /// playable, the round then goes straight to shooting.
+    pub fn is_card_playable(&self, card: &ShopCardType, has_placed_gadget: bool) -> bool {
+        !card.needs_placed_gadget()
+            || has_placed_gadget
+            || self
+                .current_hand
+                .iter()
+                .all(|card| card.needs_placed_gadget())
+    }
+
+    pub fn fill_hand(&mut self, rng: &mut Entropy<WyRand>) {
+        while self.current_hand.len() < 3 {
+            let next_card = self.next_card(rng);
+            self.current_hand.push(next_card);
+        }
+    }
+
+    /// Ball cards take effect right away, all other cards are shuffled into the deck.
+    pub fn receive_card(
+        &mut self,
+        card: ShopCardType,
+        game_resources: &GameResources,
+        rng: &mut Entropy<WyRand>,
+    ) {
+        match card {
+            ShopCardType::OneMoreBallCard => self.balls_left += 1,
+            ShopCardType::MoreBallsCard => self.balls_left += game_resources.extra_balls,
+            _ => {
+                self.discard_pile.push(card);
+                self.reshuffle_deck(rng);
+            }
+        }
+    }
+
     pub fn shuffle_deck(&mut self, rng: &mut Entropy<WyRand>) {
         self.widget_deck.shuffle(rng)
     }
@@ -96,6 +249,8 @@ impl Player {
         self.widget_deck.shuffle(rng);
         self.discard_pile.clear();
         self.current_hand.clear();
+        self.card_levels.clear();
+        self.shops_without_rare = 0;
     }
 }
 #[derive(Component, Debug)]
@@ -121,24 +276,38 @@ pub struct BallSpawnPoint;
 #[component(on_insert=BallCannon::on_ball_cannon_added)]
 pub struct BallCannon {
     pub power: f32,
+    pub min_power: f32,
     pub max_power: f32,
     pub gain: f32,
     pub is_increasing_power: bool,
+    /// rotation the cannon was set up with, aiming is limited to [`BallCannon::AIM_ARC`] around it
+    pub base_angle: f32,
 }
 
 impl Default for BallCannon {
     fn default() -> Self {
         BallCannon {
             power: 1000.0,
+            min_power: 300.0,
             max_power: 1500.0,
             gain: 1000.0,
             is_increasing_power: false,
+            base_angle: 0.0,
         }
     }
 }
 impl BallCannon {
+    /// how far the cannon can be turned to either side
+    pub const AIM_ARC: f32 = FRAC_PI_6;
+    /// power the trajectory preview assumes while the cannon isn't charging
+    pub const PREVIEW_POWER: f32 = 1000.0;
+
     fn on_ball_cannon_added(mut world: DeferredWorld, context: HookContext) {
         let cannon_transform = world.get::<Transform>(context.entity).unwrap().clone();
+        world
+            .get_mut::<BallCannon>(context.entity)
+            .unwrap()
+            .base_angle = cannon_transform.rotation.to_euler(EulerRot::XYZ).2;
 
         world.commands().spawn((
             UiClickOnCannonText,
@@ -147,7 +316,7 @@ impl BallCannon {
                 cannon_transform.rotation.inverse() * Vec3::new(10.0, -50.0, 0.0),
             )
                 .with_rotation(cannon_transform.rotation.inverse()),
-            Text2d("Click to Fire!".to_string()),
+            Text2d("Hold to Charge!".to_string()),
             TextFont {
                 font_size: 14.0,
                 ..default()
@@ -155,6 +324,14 @@ impl BallCannon {
             Visibility::Hidden,
         ));
 
+        // empty until the cannon is charged
+        world.commands().spawn((
+            IndicatorGauge,
+            ChildOf(context.entity),
+            Sprite::from_color(tailwind::YELLOW_400, Vec2::new(8.0, 50.0)),
+            Transform::from_xyz(0.0, -25.0, 0.1).with_scale(Vec3::new(1.0, 0.0, 1.0)),
+        ));
+
         world.commands().queue(move |world: &mut World| {
             let game_resources = world.get_resource::<GameResources>().unwrap();
             let image = game_resources.gadget_images[&GadgetType::BallCannonType].clone();
@@ -173,46 +350,36 @@ impl BallCannon {
             BallCannon::default(),
             Collider::rectangle(25.0, 50.0),
             RigidBody::Static,
-            observers![BallCannon::on_pressed, BallCannon::on_released],
+            observers![BallCannon::on_pressed],
         )
     }
 
-    fn on_pressed(
-        _trigger: Trigger<Pointer<Pressed>>,
-        mut commands: Commands,
-        // mut q_spitter: Query<&mut BallSpitter>,
-        q_balls: Query<Entity, With<PlayerBall>>,
-    ) {
-        for ball_entity in q_balls.iter() {
-            commands.entity(ball_entity).despawn();
-        }
-        // let mut spitter = q_spitter.get_mut(trigger.target).unwrap();
-        // spitter.is_increasing_power = true;
+    /// Keeps `angle` within the aiming arc.
+    pub fn clamp_aim(&self, angle: f32) -> f32 {
+        let offset = (angle - self.base_angle + PI).rem_euclid(TAU) - PI;
+        self.base_angle + offset.clamp(-BallCannon::AIM_ARC, BallCannon::AIM_ARC)
     }
 
-    fn on_released(
-        trigger: Trigger<Pointer<Released>>,
+    fn on_pressed(
+        trigger: Trigger<Pointer<Pressed>>,
         mut commands: Commands,
-        mut q_spitter: Query<(&mut BallCannon, &Transform)>,
+        mut q_cannon: Query<&mut BallCannon>,
         state: Res<State<LevelState>>,
-        mut next_state: ResMut<NextState<LevelState>>,
+        playback: Option<Res<ReplayPlayback>>,
     ) {
-        if !matches!(state.get(), LevelState::ShootBall) {
+        if playback.is_some() {
             return;
         }
-        let (mut spitter, spitter_transform) = q_spitter.get_mut(trigger.target).unwrap();
-        spitter.is_increasing_power = false;
-        if spitter.power == 0.0 {
-            return;
+        match state.get() {
+            // the balls are removed on the next physics tick, so a replay can do it on the same one
+            LevelState::BallBouncing => commands.insert_resource(PendingBallClear),
+            LevelState::ShootBall => {
+                let mut cannon = q_cannon.get_mut(trigger.target).unwrap();
+                cannon.power = cannon.min_power;
+                cannon.is_increasing_power = true;
+            }
+            _ => {}
         }
-        let forward = spitter_transform.rotation * Vec3::Y;
-        let forward_2d = forward.truncate();
-        commands.spawn((
-            PlayerBall,
-            Transform::from_translation(spitter_transform.translation).with_scale(Vec3::splat(0.5)),
-            LinearVelocity(forward_2d * spitter.power),
-        ));
-        next_state.set(LevelState::BallBouncing);
     }
 }
 #[derive(Component, Debug, Reflect)]
@@ -337,3 +504,36 @@ impl DestroyOnStandingStill {
         }
     }
 }
+
+/// Gadget hits of a ball in quick succession, every hit in the chain is worth more.
+#[derive(Component, Debug, Reflect)]
+pub struct Combo {
+    pub hits: usize,
+    /// runs out when the ball goes too long without scoring, which ends the chain
+    pub window: Timer,
+}
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self {
+            hits: 0,
+            window: Timer::from_seconds(Combo::WINDOW_SECONDS, TimerMode::Once),
+        }
+    }
+}
+
+impl Combo {
+    pub const WINDOW_SECONDS: f32 = 1.5;
+    pub const MAX_MULTIPLIER: usize = 5;
+
+    /// Counts a scoring hit and returns the multiplier for its points.
+    pub fn hit(&mut self) -> usize {
+        self.hits += 1;
+        self.window.reset();
+        self.multiplier()
+    }
+
+    pub fn multiplier(&self) -> usize {
+        self.hits.clamp(1, Combo::MAX_MULTIPLIER)
+    }
+}
@@ -1,4 +1,6 @@
 use crate::cards::components::{ShopCard, ShopCardType};
+use crate::game_ui::components::Forbidden;
+use crate::replay::resources::ReplayPlayback;
 use bevy::ecs::component::HookContext;
 use bevy::prelude::*;
 use bevy_bundled_observers::bevy_ecs::world::DeferredWorld;
@@ -26,7 +28,12 @@ impl OnGadgetCardSelected {
         trigger: Trigger<Pointer<Click>>,
         mut commands: Commands,
         q_card: Query<&ShopCard>,
+        q_forbidden: Query<(), With<Forbidden>>,
+        playback: Option<Res<ReplayPlayback>>,
     ) {
+        if playback.is_some() || q_forbidden.contains(trigger.target) {
+            return;
+        }
         let shop_card = q_card.get(trigger.target).unwrap();
         commands.trigger_targets(
             OnGadgetCardSelected::new(shop_card.card_type.clone()),
@@ -42,10 +49,80 @@ pub struct OnCoinCollected;
 #[derive(Event, Reflect, Debug)]
 pub struct RequestToPlaceCoins {
     pub amount: usize,
+    /// card of the gadget that spawned the coins, `None` for the coins of a new level
+    pub source: Option<ShopCardType>,
 }
 
 impl RequestToPlaceCoins {
     pub fn new(amount: usize) -> Self {
-        Self { amount }
+        Self {
+            amount,
+            source: None,
+        }
     }
-}
\ No newline at end of file
+
+    pub fn with_source(mut self, source: ShopCardType) -> Self {
+        self.source = Some(source);
+        self
+    }
+}
+/// Places the current widget, sent by the mouse or by a replay.
+#[derive(Event, Reflect, Debug)]
+pub struct RequestToPlaceGadget {
+    pub translation: Vec3,
+    pub rotation: f32,
+}
+
+impl RequestToPlaceGadget {
+    pub fn new(translation: Vec3, rotation: f32) -> Self {
+        Self {
+            translation,
+            rotation,
+        }
+    }
+}
+
+/// Picks a placed gadget to recycle or move it, `None` skips the pick.
+#[derive(Event, Reflect, Debug)]
+pub struct RequestToPickPlacedGadget {
+    pub gadget: Option<Entity>,
+}
+
+/// Fires the cannon turned to `angle`, the rotation around z in radians.
+#[derive(Event, Reflect, Debug)]
+pub struct RequestToFireCannon {
+    pub power: f32,
+    pub angle: f32,
+}
+
+#[derive(Event, Reflect, Debug)]
+pub struct RequestToLeaveShop;
+
+/// Pays for new shop offers, the free ones stay.
+#[derive(Event, Reflect, Debug)]
+pub struct RequestToRerollShop;
+
+/// Sent once a reroll is paid, whoever shows the offers replaces them.
+#[derive(Event, Reflect, Debug)]
+pub struct OnShopRerolled;
+
+#[derive(Reflect, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShopService {
+    RemoveCard,
+    UpgradeCard,
+}
+
+/// Pays for a shop service applied to one card of the deck.
+#[derive(Event, Reflect, Debug)]
+pub struct RequestShopService {
+    pub service: ShopService,
+    pub card: ShopCardType,
+}
+
+/// Continues after the round summary.
+#[derive(Event, Reflect, Debug)]
+pub struct RequestToContinueRound;
+
+/// Opens the deck viewer, or closes it when it is open.
+#[derive(Event, Reflect, Debug)]
+pub struct ToggleDeckViewer;
@@ -20,6 +20,8 @@ pub enum LevelState {
     LevelStart,
     WidgetSelection,
     PlaceWidget,
+    RecycleGadget,
+    RearrangeGadget,
     ShootBall,
     BallBouncing,
     EndOfRound,
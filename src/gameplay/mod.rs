@@ -1,35 +1,76 @@
 use crate::gameplay::game_states::*;
+use crate::gameplay::resources::{
+    FlipperInput, PendingBallClear, RoundBreakdown, RunModifiers, ShopRerolls,
+};
+use crate::replay::ReplayInputSet;
+use crate::replay::resources::ReplayPlayback;
 use crate::gameplay::systems::*;
 use bevy::prelude::*;
-use crate::gadgets::systems::gravity_inverse_field_system;
+use crate::gadgets::systems::{
+    combo_timeout_system, draw_portal_link_system, flipper_control_system, flipper_input_system,
+    gravity_inverse_field_system, magnet_system, portal_cooldown_system,
+    reload_balance_config_system, reset_flippers, store_flipper_rest_angle,
+};
 
 pub mod components;
 pub mod events;
 pub mod game_states;
+pub mod resources;
 mod systems;
+#[cfg(test)]
+mod tests;
 
 pub struct GameplayPlugin;
 
 impl Plugin for GameplayPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<RunModifiers>();
+        app.init_resource::<FlipperInput>();
+        app.init_resource::<RoundBreakdown>();
+        app.init_resource::<ShopRerolls>();
         app.add_systems(Startup, basic_setup);
         app.add_systems(
             Update,
-            widget_placement_system.run_if(in_state(LevelState::PlaceWidget)),
+            widget_placement_system.run_if(
+                in_state(LevelState::PlaceWidget).and(not(resource_exists::<ReplayPlayback>)),
+            ),
         );
         app.add_systems(
             Update,
-            increase_power_gauge_system.run_if(in_state(LevelState::ShootBall)),
+            select_placed_gadget_system.run_if(
+                in_state(LevelState::RecycleGadget).or(in_state(LevelState::RearrangeGadget)),
+            ),
         );
         app.add_systems(
             Update,
-            ball_left_play_area_system.run_if(in_state(LevelState::BallBouncing)),
+            (aim_cannon_system, increase_power_gauge_system, release_cannon_system)
+                .chain()
+                .run_if(
+                    in_state(LevelState::ShootBall).and(not(resource_exists::<ReplayPlayback>)),
+                ),
         );
+        app.add_systems(OnExit(LevelState::ShootBall), empty_power_gauge);
         app.add_systems(
             Update,
+            ball_left_play_area_system.run_if(in_state(LevelState::BallBouncing)),
+        );
+        // everything that changes the course of the ball runs on the physics ticks, so a replay
+        // plays out the same no matter the frame rate
+        app.add_systems(
+            FixedUpdate,
             destroy_when_standing_still_system.run_if(in_state(LevelState::BallBouncing)),
         );
-        app.add_systems(FixedUpdate, draw_trajectory_system);
+        app.add_systems(
+            FixedUpdate,
+            combo_timeout_system.run_if(in_state(LevelState::BallBouncing)),
+        );
+        app.add_systems(
+            FixedUpdate,
+            clear_balls_system
+                .after(ReplayInputSet)
+                .run_if(resource_exists::<PendingBallClear>),
+        );
+        app.add_systems(OnExit(LevelState::BallBouncing), cancel_ball_clear);
         app.add_systems(FixedPostUpdate, clamp_max_ball_velocity);
         app.add_systems(
             Update,
@@ -39,17 +80,60 @@ impl Plugin for GameplayPlugin {
 
         app.add_systems(OnExit(LevelState::WidgetSelection), reactivate_gadgets);
         app.add_systems(OnEnter(LevelState::LevelStart), restarting_level);
+        app.add_systems(OnEnter(LevelState::BallBouncing), reset_round_breakdown);
         app.add_systems(OnEnter(LevelState::EndOfRound), end_of_round_system);
-        app.add_systems(Update, gravity_inverse_field_system);
+        app.add_systems(FixedUpdate, magnet_system);
+        app.add_systems(FixedUpdate, portal_cooldown_system);
+        app.add_systems(Update, reload_balance_config_system);
+
+        app.add_systems(
+            Update,
+            flipper_input_system.run_if(
+                in_state(LevelState::BallBouncing).and(not(resource_exists::<ReplayPlayback>)),
+            ),
+        );
+        app.add_systems(
+            FixedUpdate,
+            flipper_control_system
+                .after(ReplayInputSet)
+                .run_if(in_state(LevelState::BallBouncing)),
+        );
+        app.add_systems(OnEnter(LevelState::BallBouncing), store_flipper_rest_angle);
+        app.add_systems(OnExit(LevelState::BallBouncing), reset_flippers);
 
         app.add_systems(OnExit(LevelState::Shop), on_exit_shop);
 
         app.add_observer(on_gadget_card_selected);
+        app.add_observer(on_place_gadget_request);
+        app.add_observer(on_pick_placed_gadget_request);
+        app.add_observer(on_fire_cannon_request);
+        app.add_observer(on_continue_round_request);
         app.add_observer(on_coin_collected);
 
         app.add_observer(on_gadget_deactivated_added);
         app.add_observer(on_gadget_deactivated_removed);
         app.add_observer(on_click_on_shop_card_system);
+        app.add_observer(on_shop_service_request);
+        app.add_observer(on_reroll_shop_request);
         app.add_observer(on_place_coins_request_system);
     }
 }
+
+/// Drawing done with `Shape2dPlugin`, left out of the headless simulation.
+pub struct GameplayVisualsPlugin;
+
+impl Plugin for GameplayVisualsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(FixedUpdate, draw_trajectory_system);
+        app.add_systems(
+            Update,
+            draw_trajectory_preview_system
+                .run_if(in_state(LevelState::PlaceWidget).or(in_state(LevelState::ShootBall))),
+        );
+        app.add_systems(Update, gravity_inverse_field_system);
+        app.add_systems(
+            Update,
+            draw_portal_link_system.run_if(in_state(LevelState::PlaceWidget)),
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use crate::cards::components::ShopCardType;
+use bevy::prelude::*;
+use bevy_rand::prelude::{Entropy, WyRand};
+use chrono::NaiveDate;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Seed of the current run. The text is what players share, the value is derived from it.
+#[derive(Resource, Debug, Clone, PartialEq, Eq)]
+pub struct RunSeed {
+    pub text: String,
+    pub value: u64,
+}
+
+impl RunSeed {
+    pub const ALPHABET: &'static [u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    pub const LENGTH: usize = 8;
+    pub const MAX_LENGTH: usize = 16;
+
+    pub fn from_text(text: &str) -> Self {
+        let text = text.trim().to_uppercase();
+        // FNV-1a, so the same text results in the same seed on every platform and build
+        let mut value: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in text.bytes() {
+            value ^= byte as u64;
+            value = value.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Self { text, value }
+    }
+
+    pub fn random(rng: &mut Entropy<WyRand>) -> Self {
+        let text: String = (0..Self::LENGTH)
+            .map(|_| Self::ALPHABET[rng.random_range(0..Self::ALPHABET.len())] as char)
+            .collect();
+        Self::from_text(&text)
+    }
+}
+
+/// One RNG stream per subsystem, all forked from the run seed, so using one of them never shifts
+/// the results of another. The streams are saved with the run, so a continued run draws the same
+/// cards and coins as one that was never interrupted.
+#[derive(Resource, Clone, Serialize, Deserialize)]
+pub struct RunRng {
+    pub cannon: Entropy<WyRand>,
+    pub deck: Entropy<WyRand>,
+    pub coins: Entropy<WyRand>,
+    pub shop: Entropy<WyRand>,
+    pub tips: Entropy<WyRand>,
+}
+
+impl RunRng {
+    pub fn from_seed(seed: &RunSeed) -> Self {
+        let mut root = Entropy::<WyRand>::seed_from_u64(seed.value);
+        // the order of the forks is part of the seed format, new streams go at the end
+        Self {
+            cannon: Entropy::from_rng(&mut root),
+            deck: Entropy::from_rng(&mut root),
+            coins: Entropy::from_rng(&mut root),
+            shop: Entropy::from_rng(&mut root),
+            tips: Entropy::from_rng(&mut root),
+        }
+    }
+}
+
+/// Rerolls bought during the current shop visit, each one costs more than the last.
+#[derive(Resource, Debug, Default)]
+pub struct ShopRerolls(pub usize);
+
+/// Rule changes for the current run, the default is a normal run.
+#[derive(Resource, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunModifiers {
+    /// set for daily challenges, formatted as `YYYY-MM-DD`
+    pub daily: Option<String>,
+    pub starter_deck: Option<Vec<ShopCardType>>,
+    pub balls_per_level: Option<usize>,
+    pub banned_cards: Vec<ShopCardType>,
+}
+
+impl RunModifiers {
+    const DAILY_STARTER_DECKS: &'static [&'static [ShopCardType]] = &[
+        &[
+            ShopCardType::SquareBlockCard,
+            ShopCardType::SquareBlockCard,
+            ShopCardType::SquareBlockCard,
+            ShopCardType::SquareBlockCard,
+            ShopCardType::SquareBlockCard,
+            ShopCardType::SquareBlockCard,
+            ShopCardType::WideBlockCard,
+            ShopCardType::WideBlockCard,
+            ShopCardType::WideBlockCard,
+            ShopCardType::BumperCard,
+        ],
+        &[
+            ShopCardType::BumperCard,
+            ShopCardType::BumperCard,
+            ShopCardType::BumperCard,
+            ShopCardType::BumperCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::CoinBumperCard,
+            ShopCardType::CoinBumperCard,
+        ],
+        &[
+            ShopCardType::WideBlockCard,
+            ShopCardType::WideBlockCard,
+            ShopCardType::WideBlockCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::TriangleBlockCard,
+            ShopCardType::HighFrictionBlockCard,
+            ShopCardType::HighFrictionBlockCard,
+            ShopCardType::BumperCard,
+            ShopCardType::BumperCard,
+            ShopCardType::MagnetCard,
+        ],
+    ];
+
+    const DAILY_BANNABLE_CARDS: &'static [ShopCardType] = &[
+        ShopCardType::BumperCard,
+        ShopCardType::CoinBumperCard,
+        ShopCardType::MagnetCard,
+        ShopCardType::FlipperCard,
+        ShopCardType::MultiBallCard,
+        ShopCardType::PortalCard,
+        ShopCardType::RecycleGadgetCard,
+        ShopCardType::RearrangeGadgetCard,
+    ];
+
+    /// Seed and rules of the daily challenge, the same for everyone on the same date.
+    pub fn daily(date: NaiveDate) -> (RunSeed, Self) {
+        let run_seed = RunSeed::from_text(&format!("DAILY{}", date.format("%Y%m%d")));
+        // kept apart from the run streams, the rules must not correlate with the first fork
+        let mut rng = Entropy::<WyRand>::seed_from_u64(!run_seed.value);
+
+        let starter_deck = Self::DAILY_STARTER_DECKS.choose(&mut rng).unwrap().to_vec();
+        let balls_per_level = rng.random_range(3..=7);
+        let banned_card = *Self::DAILY_BANNABLE_CARDS.choose(&mut rng).unwrap();
+
+        let modifiers = Self {
+            daily: Some(date.format("%Y-%m-%d").to_string()),
+            starter_deck: Some(starter_deck),
+            balls_per_level: Some(balls_per_level),
+            banned_cards: vec![banned_card],
+        };
+        (run_seed, modifiers)
+    }
+}
+
+/// Whether the flippers are held up, written by the input or by a replay and read every physics
+/// tick.
+#[derive(Resource, Debug, Default, PartialEq)]
+pub struct FlipperInput {
+    pub is_flipping: bool,
+}
+
+/// The balls in play are removed on the next physics tick.
+#[derive(Resource, Debug, Default)]
+pub struct PendingBallClear;
+
+/// What each gadget scored since the ball was fired, listed at the end of the round.
+#[derive(Resource, Debug, Default)]
+pub struct RoundBreakdown {
+    pub gadgets: HashMap<Entity, GadgetScore>,
+    pub coins: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct GadgetScore {
+    /// `None` for gadgets that were not placed from a card
+    pub card: Option<ShopCardType>,
+    pub hits: usize,
+    /// including the combo bonus
+    pub points: usize,
+    pub combo_bonus: usize,
+}
+
+impl RoundBreakdown {
+    pub fn add_hit(
+        &mut self,
+        gadget: Entity,
+        card: Option<ShopCardType>,
+        points: usize,
+        multiplier: usize,
+    ) {
+        let gadget_score = self.gadgets.entry(gadget).or_default();
+        gadget_score.card = card;
+        gadget_score.hits += 1;
+        gadget_score.points += points * multiplier;
+        gadget_score.combo_bonus += points * (multiplier - 1);
+    }
+
+    /// Best scoring gadgets first.
+    pub fn sorted_gadgets(&self) -> Vec<&GadgetScore> {
+        let mut gadgets: Vec<(&Entity, &GadgetScore)> = self.gadgets.iter().collect();
+        gadgets.sort_by(|(entity_a, a), (entity_b, b)| {
+            b.points.cmp(&a.points).then(entity_a.cmp(entity_b))
+        });
+        gadgets.into_iter().map(|(_, gadget_score)| gadget_score).collect()
+    }
+
+    pub fn points(&self) -> usize {
+        self.gadgets
+            .values()
+            .map(|gadget_score| gadget_score.points)
+            .sum()
+    }
+
+    pub fn combo_bonus(&self) -> usize {
+        self.gadgets
+            .values()
+            .map(|gadget_score| gadget_score.combo_bonus)
+            .sum()
+    }
+}
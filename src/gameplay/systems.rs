@@ -6,8 +6,13 @@ use crate::game_ui::components::Forbidden;
 use crate::gameplay::components::*;
 use crate::gameplay::events::*;
 use crate::gameplay::game_states::LevelState;
+use crate::gameplay::resources::{
+    PendingBallClear, RoundBreakdown, RunModifiers, RunRng, RunSeed, ShopRerolls,
+};
 use crate::general::components::*;
 use crate::general::resources::GameCursor;
+use crate::replay::resources::{RecordReplayAction, ReplayAction, ReplayPlayback};
+use crate::save::resources::PendingRunRestore;
 use avian2d::prelude::*;
 use bevy::color::palettes::tailwind;
 use bevy::input::mouse::MouseWheel;
@@ -17,11 +22,14 @@ use bevy_easings::{Ease, EaseFunction, EasingType};
 use bevy_rand::prelude::*;
 use bevy_simple_subsecond_system::hot;
 use bevy_vector_shapes::prelude::*;
-use std::f32::consts::TAU;
+use std::f32::consts::{FRAC_PI_2, TAU};
 use std::time::Duration;
 
 pub fn basic_setup(mut commands: Commands, mut rng: GlobalEntropy<WyRand>) {
-    commands.spawn((Name::new("Player"), Player::new(5, &mut rng)));
+    commands.spawn((
+        Name::new("Player"),
+        Player::new(&mut rng),
+    ));
 }
 
 // #[hot]
@@ -31,18 +39,32 @@ pub fn widget_placement_system(
     mut mouse_scroll_event: EventReader<MouseWheel>,
     mouse_input: Res<ButtonInput<MouseButton>>,
     key_input: Res<ButtonInput<KeyCode>>,
-    mut player: Single<&mut Player>,
-    mut q_gadget: Query<(Entity, &mut Transform, Option<&SpriteVisual>, &Collider, &CollisionLayers, Has<CanBeRotated>)>,
+    player: Single<&Player>,
+    mut q_gadget: Query<(
+        &mut Transform,
+        Option<&SpriteVisual>,
+        &Collider,
+        &CollisionLayers,
+        Has<CanBeRotated>,
+        Option<&GadgetTint>,
+        Has<GadgetDeactivated>,
+    )>,
     mut sprite_query: Query<&mut Sprite>,
     spatial_query: SpatialQuery,
-    mut next_state: ResMut<NextState<LevelState>>,
 ) {
     let Some(current_widget) = player.current_widget else {
         return;
     };
 
-    let Ok((widget_entity, mut widget_transform, sprite_visual, collider, layers, can_be_rotated)) =
-        q_gadget.get_mut(current_widget)
+    let Ok((
+        mut widget_transform,
+        sprite_visual,
+        collider,
+        layers,
+        can_be_rotated,
+        gadget_tint,
+        is_deactivated,
+    )) = q_gadget.get_mut(current_widget)
     else {
         return;
     };
@@ -80,25 +102,88 @@ pub fn widget_placement_system(
         if is_intersecting {
             sprite.color = tailwind::RED_500.into();
         } else {
-            sprite.color = Color::WHITE;
+            sprite.color = gadget_sprite_color(gadget_tint, is_deactivated);
         }
     }
 
     widget_transform.translation = game_cursor.position;
 
     if !is_intersecting && mouse_input.just_pressed(MouseButton::Left) {
-        commands
-            .entity(widget_entity)
-            .insert((PlayerPlacedGadget, Pickable::IGNORE))
-            .remove::<Preview>();
-
-        if let Some(sprite_visual) = sprite_visual {
-            let mut sprite = sprite_query.get_mut(**sprite_visual).unwrap();
-            sprite.color.set_alpha(1.0);
+        commands.trigger(RequestToPlaceGadget::new(
+            widget_transform.translation,
+            widget_transform.rotation.to_euler(EulerRot::XYZ).2,
+        ));
+    }
+}
+
+pub fn on_place_gadget_request(
+    trigger: Trigger<RequestToPlaceGadget>,
+    mut commands: Commands,
+    mut player: Single<&mut Player>,
+    mut q_gadget: Query<(
+        &mut Transform,
+        Option<&SpriteVisual>,
+        Option<&GadgetTint>,
+        Has<GadgetDeactivated>,
+    )>,
+    mut q_portal: Query<(&mut Portal, Option<&SpawnedFromCard>)>,
+    q_placed: Query<(), With<PlayerPlacedGadget>>,
+    mut sprite_query: Query<&mut Sprite>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    let Some(widget_entity) = player.current_widget else {
+        return;
+    };
+    let Ok((mut widget_transform, sprite_visual, gadget_tint, is_deactivated)) =
+        q_gadget.get_mut(widget_entity)
+    else {
+        return;
+    };
+
+    widget_transform.translation = trigger.translation;
+    widget_transform.rotation = Quat::from_rotation_z(trigger.rotation);
+    commands.trigger(RecordReplayAction(ReplayAction::PlaceGadget {
+        translation: trigger.translation.to_array(),
+        rotation: trigger.rotation,
+    }));
+
+    commands
+        .entity(widget_entity)
+        .insert((PlayerPlacedGadget, Pickable::IGNORE))
+        .remove::<Preview>();
+
+    if let Some(sprite_visual) = sprite_visual {
+        let mut sprite = sprite_query.get_mut(**sprite_visual).unwrap();
+        sprite.color = gadget_sprite_color(gadget_tint, is_deactivated);
+        sprite.color.set_alpha(1.0);
+    }
+
+    // a portal entrance brings its exit along, both are placed with the same card
+    if let Ok((mut portal, spawned_from_card)) = q_portal.get_mut(widget_entity) {
+        match portal.linked_portal {
+            None => {
+                let mut exit_commands =
+                    commands.spawn((Portal::linked_to(widget_entity), Preview));
+                if let Some(spawned_from_card) = spawned_from_card {
+                    exit_commands.insert(*spawned_from_card);
+                }
+                let exit = exit_commands.id();
+                portal.linked_portal = Some(exit);
+                player.current_widget = Some(exit);
+                return;
+            }
+            // a rearranged portal was picked up together with its other end
+            Some(linked) if !q_placed.contains(linked) => {
+                commands.entity(linked).insert(Preview);
+                player.current_widget = Some(linked);
+                return;
+            }
+            Some(_) => {}
         }
-        player.current_widget = None;
-        next_state.set(LevelState::ShootBall);
     }
+
+    player.current_widget = None;
+    next_state.set(LevelState::ShootBall);
 }
 
 #[hot]
@@ -114,13 +199,98 @@ pub fn increase_power_gauge_system(
         spitter.power = spitter
             .max_power
             .min(spitter.power + time.delta_secs() * spitter.gain);
-        let mut indicator_transform = q_indicator.get_mut(children[0]).unwrap();
         let power_gauge = spitter.power / spitter.max_power;
-        indicator_transform.translation = Vec3::new(0.0, -(1.0 - power_gauge) * 25.0, 0.1);
-        indicator_transform.scale = Vec3::new(1.0, power_gauge, 1.0);
+        for child in children.iter() {
+            if let Ok(mut indicator_transform) = q_indicator.get_mut(child) {
+                indicator_transform.translation =
+                    Vec3::new(0.0, -(1.0 - power_gauge) * 25.0, 0.1);
+                indicator_transform.scale = Vec3::new(1.0, power_gauge, 1.0);
+            }
+        }
+    }
+}
+
+pub fn empty_power_gauge(
+    mut q_spitter: Query<&mut BallCannon>,
+    mut q_indicator: Query<&mut Transform, With<IndicatorGauge>>,
+) {
+    for mut spitter in q_spitter.iter_mut() {
+        spitter.is_increasing_power = false;
+    }
+    for mut indicator_transform in q_indicator.iter_mut() {
+        indicator_transform.translation = Vec3::new(0.0, -25.0, 0.1);
+        indicator_transform.scale = Vec3::new(1.0, 0.0, 1.0);
     }
 }
 
+/// Turns the cannon towards the cursor, as far as its arc allows.
+pub fn aim_cannon_system(
+    game_cursor: Res<GameCursor>,
+    mut q_cannon: Query<(&BallCannon, &mut Transform)>,
+) {
+    for (cannon, mut transform) in q_cannon.iter_mut() {
+        let to_cursor = (game_cursor.position - transform.translation).truncate();
+        // right on top of the cannon the direction jumps around while clicking it
+        if to_cursor.length() < 40.0 {
+            continue;
+        }
+        // the barrel points along the local y axis
+        let angle = to_cursor.to_angle() - FRAC_PI_2;
+        transform.rotation = Quat::from_rotation_z(cannon.clamp_aim(angle));
+    }
+}
+
+/// Fires a charging cannon once the mouse is released, wherever the cursor is.
+pub fn release_cannon_system(
+    mut commands: Commands,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    mut q_cannon: Query<(&mut BallCannon, &Transform)>,
+) {
+    if !mouse_input.just_released(MouseButton::Left) {
+        return;
+    }
+    for (mut cannon, transform) in q_cannon.iter_mut() {
+        if !cannon.is_increasing_power {
+            continue;
+        }
+        cannon.is_increasing_power = false;
+        commands.trigger(RequestToFireCannon {
+            power: cannon.power,
+            angle: transform.rotation.to_euler(EulerRot::XYZ).2,
+        });
+    }
+}
+
+pub fn on_fire_cannon_request(
+    trigger: Trigger<RequestToFireCannon>,
+    mut commands: Commands,
+    mut cannon_transform: Single<&mut Transform, With<BallCannon>>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    cannon_transform.rotation = Quat::from_rotation_z(trigger.angle);
+    let forward = (cannon_transform.rotation * Vec3::Y).truncate();
+    commands.spawn(PlayerBall::bundle(
+        cannon_transform.translation,
+        forward * trigger.power,
+    ));
+    commands.trigger(RecordReplayAction(ReplayAction::FireCannon {
+        power: trigger.power,
+        angle: trigger.angle,
+    }));
+    next_state.set(LevelState::BallBouncing);
+}
+
+pub fn cancel_ball_clear(mut commands: Commands) {
+    commands.remove_resource::<PendingBallClear>();
+}
+
+pub fn clear_balls_system(mut commands: Commands, q_balls: Query<Entity, With<PlayerBall>>) {
+    for ball_entity in q_balls.iter() {
+        commands.entity(ball_entity).despawn();
+    }
+    commands.remove_resource::<PendingBallClear>();
+}
+
 #[hot]
 pub fn ball_left_play_area_system(
     mut commands: Commands,
@@ -129,13 +299,17 @@ pub fn ball_left_play_area_system(
     mut next_state: ResMut<NextState<LevelState>>,
     state: Res<State<LevelState>>,
 ) {
+    let mut balls_in_play = 0;
     for (ball_entity, transform, sleeping) in q_balls.iter() {
         if sleeping.is_some() || transform.translation.y < -600.0 {
             commands.entity(ball_entity).despawn();
+        } else {
+            balls_in_play += 1;
         }
     }
 
-    if (matches!(state.get(), LevelState::BallBouncing) && q_balls.iter().count() == 0) {
+    // with a multi ball split the round only ends once the last ball is gone
+    if matches!(state.get(), LevelState::BallBouncing) && balls_in_play == 0 {
         if player.balls_left > 0 {
             player.balls_left -= 1;
             next_state.set(LevelState::EndOfRound)
@@ -149,19 +323,29 @@ pub fn on_gadget_card_selected(
     mut commands: Commands,
     shop_cards_query: Query<Entity, With<ShopCard>>,
     mut player: Single<&mut Player>,
+    game_resources: Res<GameResources>,
     mut next_state: ResMut<NextState<LevelState>>,
 ) {
     for entity in shop_cards_query.iter() {
         commands.entity(entity).despawn();
     }
+    commands.trigger(RecordReplayAction(ReplayAction::SelectCard(
+        trigger.shop_card_type,
+    )));
+
+    match trigger.shop_card_type {
+        ShopCardType::RecycleGadgetCard => next_state.set(LevelState::RecycleGadget),
+        ShopCardType::RearrangeGadgetCard => next_state.set(LevelState::RearrangeGadget),
+        card_type => {
+            let gadget_entity = card_type
+                .spawn_gadget(&mut commands, &game_resources, player.card_level(&card_type))
+                .unwrap();
+            commands.entity(gadget_entity).insert(Preview);
+            player.current_widget = Some(gadget_entity);
+            next_state.set(LevelState::PlaceWidget);
+        }
+    }
 
-    let gadget_entity = trigger
-        .shop_card_type
-        .get_gadget_type()
-        .unwrap()
-        .spawn_widget(&mut commands);
-    commands.entity(gadget_entity).insert(Preview);
-    player.current_widget = Some(gadget_entity);
     let index = player
         .current_hand
         .iter()
@@ -169,13 +353,155 @@ pub fn on_gadget_card_selected(
         .unwrap();
     let used_card = player.current_hand.remove(index);
     player.discard_pile.push(used_card);
+}
+
+/// Color a placed gadget's sprite returns to when it is not highlighted.
+pub fn gadget_sprite_color(gadget_tint: Option<&GadgetTint>, is_deactivated: bool) -> Color {
+    if is_deactivated {
+        tailwind::GRAY_700.into()
+    } else {
+        gadget_tint.map_or(Color::WHITE, |tint| tint.0)
+    }
+}
+
+pub fn placed_gadget_under_cursor(
+    game_cursor: &GameCursor,
+    spatial_query: &SpatialQuery,
+    is_placed_gadget: impl Fn(Entity) -> bool,
+) -> Option<Entity> {
+    let filter =
+        SpatialQueryFilter::from_mask([GameLayer::GadgetsLayer, GameLayer::GadgetFieldsLayer]);
+    spatial_query
+        .point_intersections(game_cursor.position.xy(), &filter)
+        .into_iter()
+        .find(|entity| is_placed_gadget(*entity))
+}
+
+/// Lets the player pick one of the placed gadgets, either to recycle it for coins or to move it.
+pub fn select_placed_gadget_system(
+    mut commands: Commands,
+    game_cursor: Res<GameCursor>,
+    mouse_input: Res<ButtonInput<MouseButton>>,
+    spatial_query: SpatialQuery,
+    q_placed: Query<
+        (Entity, Option<&SpriteVisual>, Option<&GadgetTint>, Has<GadgetDeactivated>),
+        With<PlayerPlacedGadget>,
+    >,
+    mut sprite_query: Query<&mut Sprite>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    if q_placed.is_empty() {
+        next_state.set(LevelState::ShootBall);
+        return;
+    }
+    if playback.is_some() {
+        return;
+    }
+    if mouse_input.just_pressed(MouseButton::Right) {
+        commands.trigger(RequestToPickPlacedGadget { gadget: None });
+        return;
+    }
+
+    let hovered = placed_gadget_under_cursor(&game_cursor, &spatial_query, |entity| {
+        q_placed.contains(entity)
+    });
 
-    next_state.set(LevelState::PlaceWidget);
+    for (entity, sprite_visual, gadget_tint, is_deactivated) in q_placed.iter() {
+        let Some(sprite_visual) = sprite_visual else {
+            continue;
+        };
+        let Ok(mut sprite) = sprite_query.get_mut(**sprite_visual) else {
+            continue;
+        };
+        sprite.color = if hovered == Some(entity) {
+            tailwind::RED_500.into()
+        } else {
+            gadget_sprite_color(gadget_tint, is_deactivated)
+        };
+    }
+
+    if hovered.is_some() && mouse_input.just_pressed(MouseButton::Left) {
+        commands.trigger(RequestToPickPlacedGadget { gadget: hovered });
+    }
+}
+
+pub fn on_pick_placed_gadget_request(
+    trigger: Trigger<RequestToPickPlacedGadget>,
+    mut commands: Commands,
+    q_placed: Query<
+        (
+            &Transform,
+            Option<&SpawnedFromCard>,
+            Option<&SpriteVisual>,
+            Option<&GadgetTint>,
+            Has<GadgetDeactivated>,
+        ),
+        With<PlayerPlacedGadget>,
+    >,
+    q_portal: Query<&Portal>,
+    mut sprite_query: Query<&mut Sprite>,
+    game_resources: Res<GameResources>,
+    mut player: Single<&mut Player>,
+    state: Res<State<LevelState>>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    let Some(picked) = trigger.gadget else {
+        commands.trigger(RecordReplayAction(ReplayAction::SkipPickingGadget));
+        next_state.set(LevelState::ShootBall);
+        return;
+    };
+    let Ok((transform, spawned_from_card, sprite_visual, gadget_tint, is_deactivated)) =
+        q_placed.get(picked)
+    else {
+        return;
+    };
+    commands.trigger(RecordReplayAction(ReplayAction::PickPlacedGadget {
+        translation: transform.translation.to_array(),
+    }));
+
+    match state.get() {
+        LevelState::RecycleGadget => {
+            if let Some(spawned_from_card) = spawned_from_card {
+                player.coins += game_resources.get_price_per_card(&spawned_from_card.0);
+            }
+            if let Some(linked_portal) = q_portal
+                .get(picked)
+                .ok()
+                .and_then(|portal| portal.linked_portal)
+            {
+                commands.entity(linked_portal).try_despawn();
+            }
+            commands.entity(picked).despawn();
+            next_state.set(LevelState::ShootBall);
+        }
+        LevelState::RearrangeGadget => {
+            if let Some(sprite_visual) = sprite_visual {
+                let mut sprite = sprite_query.get_mut(**sprite_visual).unwrap();
+                sprite.color = gadget_sprite_color(gadget_tint, is_deactivated);
+            }
+            // both ends of a portal are moved, the other one is placed right after this one
+            if let Some(linked_portal) = q_portal
+                .get(picked)
+                .ok()
+                .and_then(|portal| portal.linked_portal)
+            {
+                commands.entity(linked_portal).remove::<PlayerPlacedGadget>();
+            }
+            commands
+                .entity(picked)
+                .remove::<PlayerPlacedGadget>()
+                .insert(Preview);
+            player.current_widget = Some(picked);
+            next_state.set(LevelState::PlaceWidget);
+        }
+        _ => {}
+    }
 }
 
 pub fn clamp_max_ball_velocity(mut q_ball: Query<&mut LinearVelocity, With<PlayerBall>>) {
     for mut velocity in q_ball.iter_mut() {
-        let max_velocity = 1000.0;
+        let max_velocity = PlayerBall::MAX_SPEED;
         if velocity.0.length() > max_velocity {
             velocity.0 = velocity.0.normalize() * max_velocity;
         }
@@ -186,23 +512,25 @@ pub fn on_coin_collected(
     trigger: Trigger<OnCoinCollected>,
     mut commands: Commands,
     mut player: Single<&mut Player>,
+    mut round_breakdown: ResMut<RoundBreakdown>,
 ) {
     player.coins += 1;
+    round_breakdown.coins += 1;
     commands.entity(trigger.target()).despawn();
 }
 
 pub fn on_gadget_deactivated_removed(
     trigger: Trigger<OnRemove, GadgetDeactivated>,
-    gadget_deactivated_query: Query<&SpriteVisual, With<Gadget>>,
+    gadget_deactivated_query: Query<(&SpriteVisual, Option<&GadgetTint>), With<Gadget>>,
     mut sprite_query: Query<&mut Sprite>,
 ) {
-    let Ok(sprite_entity) = gadget_deactivated_query.get(trigger.target()) else {
+    let Ok((sprite_entity, gadget_tint)) = gadget_deactivated_query.get(trigger.target()) else {
         return;
     };
     let Ok(mut sprite) = sprite_query.get_mut(**sprite_entity) else {
         return;
     };
-    sprite.color = Color::WHITE;
+    sprite.color = gadget_tint.map_or(Color::WHITE, |tint| tint.0);
 }
 pub fn on_gadget_deactivated_added(
     trigger: Trigger<OnAdd, GadgetDeactivated>,
@@ -235,40 +563,174 @@ pub fn restarting_level(
             With<PlayerPlacedGadget>,
         )>,
     >,
-    cannon_query: Single<(&BallCannon, &Transform)>,
+    cannon_query: Single<(&BallCannon, &mut Transform)>,
     mut player: Single<&mut Player>,
-    mut rng: GlobalEntropy<WyRand>,
+    run_seed: Res<RunSeed>,
+    run_modifiers: Res<RunModifiers>,
+    pending_restore: Option<Res<PendingRunRestore>>,
+    game_resources: Res<GameResources>,
+    mut next_state: ResMut<NextState<LevelState>>,
 ) {
     for entity in collectible_query.iter() {
         commands.entity(entity).try_despawn();
     }
-    player.reset(&mut rng);
+
+    if let Some(pending_restore) = pending_restore {
+        pending_restore
+            .0
+            .restore(&mut commands, &mut player, &game_resources);
+        commands.remove_resource::<PendingRunRestore>();
+        next_state.set(LevelState::WidgetSelection);
+        return;
+    }
+
+    // a retry replays the same seed from the start
+    let mut run_rng = RunRng::from_seed(&run_seed);
+    player.apply_modifiers(&run_modifiers, &game_resources);
+    player.reset(&mut run_rng.deck);
+    commands.insert_resource(run_rng);
 
     commands.trigger(RequestToPlaceCoins::new(5));
 
-    let (cannon, canon_transform) = cannon_query.into_inner();
-    let forward = canon_transform.rotation * Vec3::Y;
-    let forward_2d = forward.truncate();
-    commands.spawn((
-        FakePlayerBall,
-        Transform::from_translation(canon_transform.translation).with_scale(Vec3::splat(0.5)),
-        LinearVelocity(forward_2d * cannon.power),
-    ));
+    let (cannon, mut canon_transform) = cannon_query.into_inner();
+    // a new run starts with the cannon aimed the way it was set up
+    canon_transform.rotation = Quat::from_rotation_z(cannon.base_angle);
+
+    next_state.set(LevelState::WidgetSelection);
+}
+
+/// Path of a ball fired from `origin`, simulated on physics ticks against the colliders in the
+/// world. Nothing is spawned, so no points or coins are triggered.
+pub fn predict_ball_path(
+    spatial_query: &SpatialQuery,
+    filter: &SpatialQueryFilter,
+    q_restitution: &Query<&Restitution>,
+    q_reverse_field: &Query<(), With<GravityReverseField>>,
+    q_high_friction: &Query<&HighFrictionBlock>,
+    gravity: Vec2,
+    origin: Vec2,
+    mut velocity: Vec2,
+) -> Vec<Vec2> {
+    let steps = 192;
+    let delta = 1.0 / 64.0;
+    let ball_collider = Collider::circle(PlayerBall::RADIUS);
+    let field_filter = SpatialQueryFilter::from_mask(GameLayer::GadgetFieldsLayer);
+
+    let ball_restitution = Restitution::new(PlayerBall::RESTITUTION);
+
+    let mut position = origin;
+    let mut path = vec![origin];
+    // gadgets hit in the previous tick, a contact that goes on does not start a new collision
+    let mut touching = Vec::new();
+    for _ in 0..steps {
+        let is_in_reverse_field = spatial_query
+            .shape_intersections(&ball_collider, position, 0.0, &field_filter)
+            .into_iter()
+            .any(|entity| q_reverse_field.contains(entity));
+        let gravity_scale = if is_in_reverse_field { -3.0 } else { 1.0 };
+        velocity += gravity * gravity_scale * delta;
+
+        let mut time_left = delta;
+        let mut hit_this_tick = Vec::new();
+        // a fast ball can bounce off more than one surface in a single tick
+        for _ in 0..3 {
+            let speed = velocity.length();
+            let Ok(direction) = Dir2::new(velocity) else {
+                break;
+            };
+            let config = ShapeCastConfig {
+                ignore_origin_penetration: true,
+                ..ShapeCastConfig::from_max_distance(speed * time_left)
+            };
+            let Some(hit) =
+                spatial_query.cast_shape(&ball_collider, position, 0.0, direction, &config, filter)
+            else {
+                position += velocity * time_left;
+                break;
+            };
+            position += direction * hit.distance;
+            time_left -= hit.distance / speed;
+
+            // combined the way the physics engine does, including the combine rule of the gadget
+            let restitution = q_restitution
+                .get(hit.entity)
+                .map_or(Restitution::ZERO, |restitution| *restitution);
+            let restitution = ball_restitution.combine(restitution).coefficient;
+            let normal_speed = velocity.dot(hit.normal1);
+            if normal_speed < 0.0 {
+                velocity -= (1.0 + restitution) * normal_speed * hit.normal1;
+            }
+
+            // same as `on_hit_slow_down`, once per collision
+            if let Ok(block) = q_high_friction.get(hit.entity) {
+                if !touching.contains(&hit.entity) && !hit_this_tick.contains(&hit.entity) {
+                    velocity *= 1.0 - block.velocity_drain;
+                }
+            }
+            hit_this_tick.push(hit.entity);
+        }
+        touching = hit_this_tick;
+        velocity = velocity.clamp_length_max(PlayerBall::MAX_SPEED);
+        path.push(position);
 
-    // next_state.set(LevelState::PlaceWidget);
+        // the ball is out of play there, see `ball_left_play_area_system`
+        if position.y < -600.0 {
+            break;
+        }
+    }
+    path
+}
+
+/// Redraws where the next shot goes, so the effect of the gadget being placed shows right away.
+pub fn draw_trajectory_preview_system(
+    spatial_query: SpatialQuery,
+    cannon: Single<(Entity, &BallCannon, &Transform)>,
+    q_restitution: Query<&Restitution>,
+    q_reverse_field: Query<(), With<GravityReverseField>>,
+    q_high_friction: Query<&HighFrictionBlock>,
+    q_pass_through: Query<Entity, Or<(With<Sensor>, With<Coin>)>>,
+    gravity: Res<Gravity>,
+    state: Res<State<LevelState>>,
+    mut painter: ShapePainter,
+) {
+    let (cannon_entity, cannon, cannon_transform) = cannon.into_inner();
+    let filter =
+        SpatialQueryFilter::from_mask([GameLayer::DefaultLayer, GameLayer::GadgetsLayer])
+            .with_excluded_entities(q_pass_through.iter().chain([cannon_entity]));
+    // while placing, the cannon still points wherever the last shot went, so a straight shot is shown
+    let angle = match state.get() {
+        LevelState::ShootBall => cannon_transform.rotation.to_euler(EulerRot::XYZ).2,
+        _ => cannon.base_angle,
+    };
+    let power = if cannon.is_increasing_power {
+        cannon.power
+    } else {
+        BallCannon::PREVIEW_POWER
+    };
+    let forward = (Quat::from_rotation_z(angle) * Vec3::Y).truncate();
+    let path = predict_ball_path(
+        &spatial_query,
+        &filter,
+        &q_restitution,
+        &q_reverse_field,
+        &q_high_friction,
+        gravity.0,
+        cannon_transform.translation.truncate(),
+        forward * power,
+    );
+
+    painter.color = tailwind::GRAY_300.with_alpha(0.6).into();
+    for point in path.iter().step_by(3) {
+        painter.transform.translation = point.extend(1.0);
+        painter.circle(2.0);
+    }
 }
 
 pub fn draw_trajectory_system(
-    mut commands: Commands,
-    trajectory_query: Query<(Entity, &Transform, &DrawTrajectory)>,
+    trajectory_query: Query<(&Transform, &DrawTrajectory)>,
     mut shapes: ShapeCommands,
-    mut next_state: ResMut<NextState<LevelState>>,
 ) {
-    for (entity, transform, draw_trajectory) in trajectory_query.iter() {
-        if transform.translation.y < -800.0 {
-            commands.entity(entity).despawn();
-            next_state.set(LevelState::WidgetSelection);
-        }
+    for (transform, draw_trajectory) in trajectory_query.iter() {
         shapes.transform.translation = transform.translation;
         shapes
             .circle(1.0)
@@ -281,23 +743,11 @@ pub fn end_of_round_system(
     mut player: Single<&mut Player>,
     mut shrink_at_end_of_round_query: Query<(Entity, &ShrinkAtEndOfRound, &Transform)>,
     mut remaining_rounds_query: Query<(Entity, &mut RemainingRounds)>,
-    mut next_state: ResMut<NextState<LevelState>>,
 ) {
     player.points_last_round = player.points_this_round;
     player.points_this_round = 0;
 
     info!("We are at the end of round");
-    if player.points >= player.point_for_next_level {
-        info!("We are going to the shop");
-        next_state.set(LevelState::Shop);
-    } else if player.balls_left > 0 {
-        info!("We are placing a widget");
-
-        next_state.set(LevelState::WidgetSelection);
-    } else {
-        info!("We are gameover");
-        next_state.set(LevelState::GameOver);
-    }
     // player.points = 0;
 
     for (entity, shrink, transform) in shrink_at_end_of_round_query.iter_mut() {
@@ -322,7 +772,42 @@ pub fn end_of_round_system(
     }
 }
 
-pub fn on_exit_shop(mut commands: Commands, mut player: Single<&mut Player>) {
+/// Leaves the round summary, sent by its button or by a replay.
+pub fn on_continue_round_request(
+    _: Trigger<RequestToContinueRound>,
+    mut commands: Commands,
+    player: Single<&Player>,
+    state: Res<State<LevelState>>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    if !matches!(state.get(), LevelState::EndOfRound) {
+        return;
+    }
+    commands.trigger(RecordReplayAction(ReplayAction::ContinueRound));
+
+    if player.points >= player.point_for_next_level {
+        info!("We are going to the shop");
+        next_state.set(LevelState::Shop);
+    } else if player.balls_left > 0 {
+        info!("We are placing a widget");
+
+        next_state.set(LevelState::WidgetSelection);
+    } else {
+        info!("We are gameover");
+        next_state.set(LevelState::GameOver);
+    }
+}
+
+pub fn reset_round_breakdown(mut round_breakdown: ResMut<RoundBreakdown>) {
+    *round_breakdown = RoundBreakdown::default();
+}
+
+pub fn on_exit_shop(
+    mut commands: Commands,
+    mut player: Single<&mut Player>,
+    mut shop_rerolls: ResMut<ShopRerolls>,
+) {
+    shop_rerolls.0 = 0;
     player.current_level += 1;
     player.point_for_next_level = Player::points_for_level(player.current_level);
     commands.trigger(RequestToPlaceCoins::new(5));
@@ -366,7 +851,7 @@ pub fn on_click_on_shop_card_system(
     mut player: Single<&mut Player>,
     mut commands: Commands,
     game_resources: Res<GameResources>,
-    mut rng: GlobalEntropy<WyRand>,
+    mut run_rng: ResMut<RunRng>,
 ) {
     let Ok((card, transform)) = shop_card_query.get(trigger.target()) else {
         return;
@@ -378,6 +863,7 @@ pub fn on_click_on_shop_card_system(
         return;
     }
     player.coins -= price;
+    commands.trigger(RecordReplayAction(ReplayAction::BuyCard(card.card_type)));
 
     let transform_ease = transform.ease_to_fn(
         |start| Transform {
@@ -396,14 +882,62 @@ pub fn on_click_on_shop_card_system(
     ));
     commands.entity(trigger.target()).remove::<Collider>();
 
-    match card.card_type {
-        ShopCardType::OneMoreBallCard => player.balls_left += 1,
-        ShopCardType::MoreBallsCard => player.balls_left += game_resources.balls_per_level,
-        _ => {
-            player.discard_pile.push(card.card_type);
-            player.reshuffle_deck(&mut rng);
+    player.receive_card(card.card_type, &game_resources, &mut run_rng.deck);
+}
+
+pub fn on_shop_service_request(
+    trigger: Trigger<RequestShopService>,
+    mut commands: Commands,
+    mut player: Single<&mut Player>,
+    game_resources: Res<GameResources>,
+) {
+    let card = trigger.card;
+    let (price, action) = match trigger.service {
+        ShopService::RemoveCard => {
+            if !player.can_remove_card() {
+                return;
+            }
+            (game_resources.card_removal_price, ReplayAction::RemoveCard(card))
+        }
+        ShopService::UpgradeCard => {
+            if !game_resources.is_upgradable(&card) || !player.can_upgrade_card(&card) {
+                return;
+            }
+            let price = game_resources.get_upgrade_price(player.card_level(&card));
+            (price, ReplayAction::UpgradeCard(card))
         }
+    };
+    if price > player.coins {
+        return;
     }
+
+    match trigger.service {
+        ShopService::RemoveCard => {
+            if !player.remove_card(card) {
+                return;
+            }
+        }
+        ShopService::UpgradeCard => player.upgrade_card(card),
+    }
+    player.coins -= price;
+    commands.trigger(RecordReplayAction(action));
+}
+
+pub fn on_reroll_shop_request(
+    _: Trigger<RequestToRerollShop>,
+    mut commands: Commands,
+    mut player: Single<&mut Player>,
+    mut shop_rerolls: ResMut<ShopRerolls>,
+    game_resources: Res<GameResources>,
+) {
+    let price = game_resources.get_reroll_price(shop_rerolls.0);
+    if price > player.coins {
+        return;
+    }
+    player.coins -= price;
+    shop_rerolls.0 += 1;
+    commands.trigger(RecordReplayAction(ReplayAction::RerollShop));
+    commands.trigger(OnShopRerolled);
 }
 
 pub fn decay_over_time_system(
@@ -430,7 +964,7 @@ pub fn on_place_coins_request_system(
     trigger: Trigger<RequestToPlaceCoins>,
     mut commands: Commands,
     game_resources: Res<GameResources>,
-    mut rng: GlobalEntropy<WyRand>,
+    mut run_rng: ResMut<RunRng>,
     spatial_query: SpatialQuery,
 ) {
     let mut spawned_coins = 0;
@@ -438,7 +972,7 @@ pub fn on_place_coins_request_system(
     mask.remove(GameLayer::GadgetFieldsLayer);
     let filter = SpatialQueryFilter::from_mask(mask);
     for _ in 0..100 {
-        let position = game_resources.get_random_position_in_play_area(&mut rng);
+        let position = game_resources.get_random_position_in_play_area(&mut run_rng.coins);
         let intersections = spatial_query.shape_intersections(
             &CollectibleType::CoinType.collider(),
             position,
@@ -450,10 +984,13 @@ pub fn on_place_coins_request_system(
             continue;
         }
 
-        commands.spawn((
+        let mut coin_commands = commands.spawn((
             CollectibleType::coin_bundle(),
             Transform::from_translation(position.extend(0.0)),
         ));
+        if let Some(source) = trigger.source {
+            coin_commands.insert(CoinSource(source));
+        }
         spawned_coins += 1;
 
         if spawned_coins >= trigger.amount {
@@ -0,0 +1,553 @@
+use crate::cards::components::{CardRarity, ShopCardType};
+use crate::gadgets::balance::{BalanceConfig, BalanceConfigLoaderError};
+use crate::gadgets::components::*;
+use crate::gadgets::resources::GameResources;
+use crate::gameplay::GameplayPlugin;
+use crate::gameplay::components::*;
+use crate::gameplay::events::*;
+use crate::gameplay::game_states::{AppState, LevelState};
+use crate::gameplay::resources::{RoundBreakdown, RunRng, RunSeed, ShopRerolls};
+use crate::general::resources::GameCursor;
+use avian2d::prelude::*;
+use bevy::input::InputPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy_easings::EasingsPlugin;
+use bevy_rand::prelude::*;
+use bevy_simple_subsecond_system::prelude::*;
+use rand::{RngCore, SeedableRng};
+use std::f32::consts::FRAC_PI_2;
+use std::time::Duration;
+
+/// The gameplay plugins with the balance config read in place, every update is one physics tick.
+fn test_app() -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        StatesPlugin,
+        AssetPlugin::default(),
+        TransformPlugin,
+        InputPlugin,
+    ));
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1.0 / 64.0,
+    )));
+    app.insert_resource(Time::<Fixed>::from_hz(64.0));
+    app.add_plugins(SimpleSubsecondPlugin::default());
+    app.add_plugins(EntropyPlugin::<WyRand>::default());
+    app.add_plugins(PhysicsPlugins::new(FixedPostUpdate));
+    app.add_plugins(EasingsPlugin::default());
+    app.init_asset::<BalanceConfig>();
+
+    let config = BalanceConfig::fallback();
+    let mut game_resources = GameResources::default();
+    game_resources.use_placeholder_images();
+    game_resources.apply_balance_config(&config);
+    app.insert_resource(game_resources);
+    app.init_resource::<GameCursor>();
+
+    let run_seed = RunSeed::from_text("TEST");
+    app.insert_resource(RunRng::from_seed(&run_seed));
+    app.insert_resource(run_seed);
+
+    app.add_plugins(GameplayPlugin);
+    app.insert_state(AppState::Loading);
+    app.add_sub_state::<LevelState>();
+    app
+}
+
+/// Spawns the cannon and enters `LevelStart`.
+fn start_level(app: &mut App) {
+    // the player is spawned on startup
+    app.update();
+    app.world_mut().spawn((
+        BallCannon::bundle(),
+        Transform::from_xyz(400.0, 0.0, 0.0).with_rotation(Quat::from_rotation_z(FRAC_PI_2)),
+    ));
+    app.world_mut()
+        .resource_mut::<NextState<AppState>>()
+        .set(AppState::InGame);
+    app.update();
+}
+
+fn set_level_state(app: &mut App, state: LevelState) {
+    app.world_mut()
+        .resource_mut::<NextState<LevelState>>()
+        .set(state);
+    app.update();
+}
+
+fn level_state(app: &App) -> LevelState {
+    *app.world().resource::<State<LevelState>>().get()
+}
+
+fn player(app: &mut App) -> Mut<'_, Player> {
+    let world = app.world_mut();
+    let mut query = world.query::<&mut Player>();
+    query.single_mut(world).unwrap()
+}
+
+fn count<F: bevy::ecs::query::QueryFilter>(app: &mut App) -> usize {
+    app.world_mut()
+        .query_filtered::<(), F>()
+        .iter(app.world())
+        .count()
+}
+
+/// Goes through the widget selection, places the picked gadget and fires the cannon.
+fn play_until_ball_bouncing(app: &mut App) {
+    set_level_state(app, LevelState::WidgetSelection);
+    let card = {
+        let mut run_rng = app.world_mut().remove_resource::<RunRng>().unwrap();
+        let mut player = player(app);
+        player.fill_hand(&mut run_rng.deck);
+        let card = player
+            .current_hand
+            .iter()
+            .copied()
+            .find(|card| card.get_gadget_type().is_some())
+            .unwrap();
+        app.world_mut().insert_resource(run_rng);
+        card
+    };
+    app.world_mut().trigger(OnGadgetCardSelected::new(card));
+    app.update();
+    assert_eq!(level_state(app), LevelState::PlaceWidget);
+
+    app.world_mut()
+        .trigger(RequestToPlaceGadget::new(Vec3::new(-200.0, 200.0, 0.0), 0.0));
+    app.update();
+    assert_eq!(level_state(app), LevelState::ShootBall);
+
+    app.world_mut().trigger(RequestToFireCannon {
+        power: 1000.0,
+        angle: FRAC_PI_2,
+    });
+    app.update();
+    assert_eq!(level_state(app), LevelState::BallBouncing);
+}
+
+#[test]
+fn level_start_resets_the_player() {
+    let mut app = test_app();
+    app.update();
+    player(&mut app).coins = 42;
+    start_level(&mut app);
+
+    assert_eq!(level_state(&app), LevelState::LevelStart);
+    let balls_per_level = app.world().resource::<GameResources>().balls_per_level;
+    let player = player(&mut app);
+    assert_eq!(player.coins, 0);
+    assert_eq!(player.points, 0);
+    assert_eq!(player.current_level, 0);
+    assert_eq!(player.balls_left, player.balls_per_level);
+    assert_eq!(player.balls_per_level, balls_per_level);
+    assert_eq!(player.point_for_next_level, Player::points_for_level(0));
+    assert!(player.current_hand.is_empty());
+    assert_eq!(count::<With<Coin>>(&mut app), 5);
+}
+
+#[test]
+fn selecting_a_card_creates_a_preview_to_place() {
+    let mut app = test_app();
+    start_level(&mut app);
+    set_level_state(&mut app, LevelState::WidgetSelection);
+
+    let mut run_rng = app.world_mut().remove_resource::<RunRng>().unwrap();
+    player(&mut app).fill_hand(&mut run_rng.deck);
+    app.world_mut().insert_resource(run_rng);
+    let card = player(&mut app)
+        .current_hand
+        .iter()
+        .copied()
+        .find(|card| card.get_gadget_type().is_some())
+        .unwrap();
+
+    app.world_mut().trigger(OnGadgetCardSelected::new(card));
+    app.update();
+
+    assert_eq!(level_state(&app), LevelState::PlaceWidget);
+    let player = player(&mut app);
+    assert_eq!(player.current_hand.len(), 2);
+    assert_eq!(player.discard_pile, vec![card]);
+    let current_widget = player.current_widget.unwrap();
+    assert!(app.world().get::<Preview>(current_widget).is_some());
+}
+
+#[test]
+fn placing_and_firing_starts_the_ball_bouncing() {
+    let mut app = test_app();
+    start_level(&mut app);
+    play_until_ball_bouncing(&mut app);
+
+    assert!(player(&mut app).current_widget.is_none());
+    assert_eq!(count::<With<PlayerPlacedGadget>>(&mut app), 1);
+    assert_eq!(count::<With<PlayerBall>>(&mut app), 1);
+}
+
+#[test]
+fn ball_leaving_the_play_area_ends_the_round() {
+    let mut app = test_app();
+    start_level(&mut app);
+    play_until_ball_bouncing(&mut app);
+    let balls_before = player(&mut app).balls_left;
+
+    let ball = app
+        .world_mut()
+        .query_filtered::<Entity, With<PlayerBall>>()
+        .single(app.world())
+        .unwrap();
+    let mut ball_entity = app.world_mut().entity_mut(ball);
+    ball_entity.get_mut::<Transform>().unwrap().translation.y = -700.0;
+    ball_entity.get_mut::<Position>().unwrap().0.y = -700.0;
+    app.update();
+
+    assert_eq!(player(&mut app).balls_left, balls_before - 1);
+    assert_eq!(count::<With<PlayerBall>>(&mut app), 0);
+    app.update();
+    assert_eq!(level_state(&app), LevelState::EndOfRound);
+    // the summary waits for the player
+    app.update();
+    assert_eq!(level_state(&app), LevelState::EndOfRound);
+
+    // the goal is not reached and there are balls left
+    app.world_mut().trigger(RequestToContinueRound);
+    app.update();
+    assert_eq!(level_state(&app), LevelState::WidgetSelection);
+}
+
+#[test]
+fn end_of_round_goes_to_the_shop_once_the_goal_is_reached() {
+    let mut app = test_app();
+    start_level(&mut app);
+    {
+        let mut player = player(&mut app);
+        player.points_this_round = player.point_for_next_level;
+        player.points = player.point_for_next_level;
+    }
+
+    set_level_state(&mut app, LevelState::EndOfRound);
+    let points_needed = player(&mut app).point_for_next_level;
+    assert_eq!(player(&mut app).points_last_round, points_needed);
+    assert_eq!(player(&mut app).points_this_round, 0);
+    app.world_mut().trigger(RequestToContinueRound);
+    app.update();
+    assert_eq!(level_state(&app), LevelState::Shop);
+}
+
+#[test]
+fn end_of_round_without_balls_is_game_over() {
+    let mut app = test_app();
+    start_level(&mut app);
+    player(&mut app).balls_left = 0;
+
+    set_level_state(&mut app, LevelState::EndOfRound);
+    app.world_mut().trigger(RequestToContinueRound);
+    app.update();
+    assert_eq!(level_state(&app), LevelState::GameOver);
+}
+
+#[test]
+fn leaving_the_shop_raises_the_level_and_the_goal() {
+    let mut app = test_app();
+    start_level(&mut app);
+    set_level_state(&mut app, LevelState::Shop);
+    let coins_before = count::<With<Coin>>(&mut app);
+
+    set_level_state(&mut app, LevelState::WidgetSelection);
+    let player = player(&mut app);
+    assert_eq!(player.current_level, 1);
+    assert_eq!(player.point_for_next_level, Player::points_for_level(1));
+    assert!(count::<With<Coin>>(&mut app) > coins_before);
+}
+
+#[test]
+fn round_breakdown_lists_the_best_gadget_first() {
+    let mut app = test_app();
+    let block = app.world_mut().spawn_empty().id();
+    let bumper = app.world_mut().spawn_empty().id();
+
+    let mut round_breakdown = RoundBreakdown::default();
+    round_breakdown.add_hit(block, Some(ShopCardType::WideBlockCard), 1, 1);
+    round_breakdown.add_hit(bumper, Some(ShopCardType::BumperCard), 3, 2);
+    round_breakdown.add_hit(bumper, Some(ShopCardType::BumperCard), 3, 3);
+
+    let gadgets = round_breakdown.sorted_gadgets();
+    assert_eq!(gadgets[0].card, Some(ShopCardType::BumperCard));
+    assert_eq!(gadgets[0].hits, 2);
+    assert_eq!(gadgets[0].points, 15);
+    assert_eq!(gadgets[0].combo_bonus, 9);
+    assert_eq!(gadgets[1].points, 1);
+    assert_eq!(round_breakdown.points(), 16);
+    assert_eq!(round_breakdown.combo_bonus(), 9);
+}
+
+#[test]
+fn deck_counts_are_listed_in_a_fixed_order() {
+    let mut app = test_app();
+    start_level(&mut app);
+    set_level_state(&mut app, LevelState::WidgetSelection);
+    let mut run_rng = app.world_mut().remove_resource::<RunRng>().unwrap();
+    let mut player = player(&mut app);
+    player.fill_hand(&mut run_rng.deck);
+    let card = player.current_hand.pop().unwrap();
+    player.discard_pile.push(card);
+
+    let full_deck = Player::count_cards(player.full_deck());
+    assert_eq!(full_deck, Player::count_cards(&Player::default_starter_deck()));
+    assert_eq!(
+        full_deck,
+        vec![
+            (ShopCardType::SquareBlockCard, 4),
+            (ShopCardType::WideBlockCard, 4),
+            (ShopCardType::BumperCard, 2),
+            (ShopCardType::CoinBumperCard, 1),
+        ]
+    );
+    assert_eq!(Player::count_cards(&player.discard_pile), vec![(card, 1)]);
+    let draw_pile: usize = Player::count_cards(&player.widget_deck)
+        .iter()
+        .map(|(_, count)| count)
+        .sum();
+    assert_eq!(draw_pile, player.widget_deck.len());
+}
+
+#[test]
+fn recycle_and_rearrange_need_a_placed_gadget() {
+    let mut player = Player::default();
+    player.current_hand = vec![
+        ShopCardType::RecycleGadgetCard,
+        ShopCardType::RearrangeGadgetCard,
+        ShopCardType::SquareBlockCard,
+    ];
+    assert!(!player.is_card_playable(&ShopCardType::RecycleGadgetCard, false));
+    assert!(!player.is_card_playable(&ShopCardType::RearrangeGadgetCard, false));
+    assert!(player.is_card_playable(&ShopCardType::SquareBlockCard, false));
+    assert!(player.is_card_playable(&ShopCardType::RecycleGadgetCard, true));
+
+    // a hand that can't place anything is still played, so the round goes on
+    player.current_hand.pop();
+    assert!(player.is_card_playable(&ShopCardType::RecycleGadgetCard, false));
+}
+
+#[test]
+fn shop_services_upgrade_and_remove_cards() {
+    let mut app = test_app();
+    start_level(&mut app);
+    set_level_state(&mut app, LevelState::Shop);
+    let (removal_price, upgrade_price) = {
+        let game_resources = app.world().resource::<GameResources>();
+        (
+            game_resources.card_removal_price,
+            game_resources.get_upgrade_price(0),
+        )
+    };
+    player(&mut app).coins = removal_price + upgrade_price;
+
+    app.world_mut().trigger(RequestShopService {
+        service: ShopService::UpgradeCard,
+        card: ShopCardType::BumperCard,
+    });
+    app.world_mut().trigger(RequestShopService {
+        service: ShopService::RemoveCard,
+        card: ShopCardType::WideBlockCard,
+    });
+    app.update();
+    {
+        let player = player(&mut app);
+        assert_eq!(player.card_level(&ShopCardType::BumperCard), 1);
+        assert_eq!(player.coins, 0);
+        let deck = Player::count_cards(player.full_deck());
+        assert!(deck.contains(&(ShopCardType::WideBlockCard, 3)));
+    }
+
+    // without coins nothing happens
+    app.world_mut().trigger(RequestShopService {
+        service: ShopService::RemoveCard,
+        card: ShopCardType::WideBlockCard,
+    });
+    app.update();
+    assert_eq!(player(&mut app).full_deck().count(), 10);
+
+    let game_resources = app.world().resource::<GameResources>();
+    let base = game_resources
+        .gadget_for_level(&ShopCardType::BumperCard, 0)
+        .unwrap();
+    let upgraded = game_resources
+        .gadget_for_level(&ShopCardType::BumperCard, 1)
+        .unwrap();
+    assert_eq!(
+        upgraded.activations_per_round,
+        base.activations_per_round + 1
+    );
+    assert!(!game_resources.is_upgradable(&ShopCardType::OneMoreBallCard));
+}
+
+#[test]
+fn rerolls_get_more_expensive_until_the_shop_is_left() {
+    let mut app = test_app();
+    start_level(&mut app);
+    set_level_state(&mut app, LevelState::Shop);
+    let (first_price, second_price) = {
+        let game_resources = app.world().resource::<GameResources>();
+        (
+            game_resources.get_reroll_price(0),
+            game_resources.get_reroll_price(1),
+        )
+    };
+    assert!(second_price > first_price);
+    player(&mut app).coins = first_price + second_price;
+
+    app.world_mut().trigger(RequestToRerollShop);
+    app.world_mut().trigger(RequestToRerollShop);
+    // not enough coins left for a third one
+    app.world_mut().trigger(RequestToRerollShop);
+    app.update();
+    assert_eq!(app.world().resource::<ShopRerolls>().0, 2);
+    assert_eq!(player(&mut app).coins, 0);
+
+    set_level_state(&mut app, LevelState::WidgetSelection);
+    assert_eq!(app.world().resource::<ShopRerolls>().0, 0);
+}
+
+#[test]
+fn shop_offers_a_rare_card_once_the_pity_is_reached() {
+    let app = test_app();
+    let game_resources = app.world().resource::<GameResources>();
+    let mut rng = Entropy::<WyRand>::seed_from_u64(7);
+    let has_rare = |cards: &[ShopCardType]| {
+        cards
+            .iter()
+            .any(|card| game_resources.card_rarity(card) == Some(CardRarity::Rare))
+    };
+
+    for _ in 0..20 {
+        let cards = game_resources.get_shop_cards_for_level(0, 0, &[], &mut rng);
+        assert_eq!(cards[0], ShopCardType::MoreBallsCard);
+        assert_eq!(cards.len(), 4);
+        // rare cards have no weight on the first level
+        assert!(!has_rare(&cards));
+
+        let cards =
+            game_resources.get_shop_cards_for_level(0, game_resources.rare_pity, &[], &mut rng);
+        assert!(has_rare(&cards));
+    }
+
+    // a pity that can't be paid out still fills the shop
+    let rare_cards: Vec<ShopCardType> = ShopCardType::ALL
+        .into_iter()
+        .filter(|card| game_resources.card_rarity(card) == Some(CardRarity::Rare))
+        .collect();
+    let cards =
+        game_resources.get_shop_cards_for_level(0, game_resources.rare_pity, &rare_cards, &mut rng);
+    assert_eq!(cards.len(), 4);
+
+    let mut player = Player::default();
+    player.shops_without_rare = game_resources.rare_pity;
+    player.roll_shop_offers(game_resources, &[], &mut rng);
+    assert_eq!(player.shops_without_rare, 0);
+    player.roll_shop_offers(game_resources, &[], &mut rng);
+    assert_eq!(player.shops_without_rare, 1);
+
+    // rerolling within a visit never brings the pity closer
+    for _ in 0..game_resources.rare_pity * 2 {
+        let cards = player.reroll_shop_offers(game_resources, &[], &mut rng);
+        assert!(!has_rare(&cards));
+    }
+    assert_eq!(player.shops_without_rare, 1);
+}
+
+#[test]
+fn upgraded_gadgets_keep_their_level_on_a_balance_reload() {
+    let mut app = test_app();
+    let config = BalanceConfig::fallback();
+    let handle = app
+        .world_mut()
+        .resource_mut::<Assets<BalanceConfig>>()
+        .add(config);
+    app.world_mut().resource_mut::<GameResources>().balance_config = handle.clone();
+    app.update();
+
+    let bumper = app
+        .world_mut()
+        .spawn((
+            SpawnedFromCard(ShopCardType::BumperCard),
+            UpgradeLevel(2),
+            Gadget::new(1),
+            PointsOnHit::new(1),
+        ))
+        .id();
+    {
+        let mut configs = app.world_mut().resource_mut::<Assets<BalanceConfig>>();
+        let config = configs.get_mut(&handle).unwrap();
+        let bumper_card = config.cards.get_mut(&ShopCardType::BumperCard).unwrap();
+        bumper_card.activations = Some(4);
+        bumper_card.points = Some(10);
+    }
+    app.update();
+
+    let gadget = app.world().get::<Gadget>(bumper).unwrap();
+    assert_eq!(gadget.activations_per_round, 6);
+    assert_eq!(app.world().get::<PointsOnHit>(bumper).unwrap().amount, 12);
+}
+
+#[test]
+fn balance_config_without_a_card_is_rejected() {
+    let mut config = BalanceConfig::fallback();
+    assert!(config.validate().is_ok());
+    config.cards.remove(&ShopCardType::PortalCard);
+    assert!(matches!(
+        config.validate(),
+        Err(BalanceConfigLoaderError::MissingCard(ShopCardType::PortalCard))
+    ));
+}
+
+#[test]
+fn magnet_only_pulls_coins_within_its_own_radius() {
+    let mut app = test_app();
+    app.update();
+    let magnet = MagnetGadget {
+        time_left: Duration::from_secs(5),
+        ..default()
+    };
+    let radius = magnet.radius;
+    app.world_mut().spawn((magnet, Transform::default()));
+    let ball_position = Vec3::new(radius + 150.0, 0.0, 0.0);
+    app.world_mut()
+        .spawn(PlayerBall::bundle(ball_position, Vec2::ZERO));
+    let near_magnet = Vec3::new(radius * 0.5, 0.0, 0.0);
+    let near_ball = Vec3::new(radius + 100.0, 0.0, 0.0);
+    let coin_near_magnet = app
+        .world_mut()
+        .spawn((CollectibleType::coin_bundle(), Transform::from_translation(near_magnet)))
+        .id();
+    let coin_near_ball = app
+        .world_mut()
+        .spawn((CollectibleType::coin_bundle(), Transform::from_translation(near_ball)))
+        .id();
+
+    for _ in 0..4 {
+        app.update();
+    }
+
+    let translation =
+        |app: &App, entity: Entity| app.world().get::<Transform>(entity).unwrap().translation;
+    // pulled towards the ball
+    assert!(translation(&app, coin_near_magnet).x > near_magnet.x);
+    // close to the ball but out of the magnet's reach
+    assert_eq!(translation(&app, coin_near_ball), near_ball);
+}
+
+#[test]
+fn saved_rng_streams_continue_where_they_left_off() {
+    let mut run_rng = RunRng::from_seed(&RunSeed::from_text("SAVED"));
+    run_rng.deck.next_u64();
+    run_rng.shop.next_u64();
+
+    let text = ron::to_string(&run_rng).unwrap();
+    let mut restored: RunRng = ron::from_str(&text).unwrap();
+    assert_eq!(restored.deck.next_u64(), run_rng.deck.next_u64());
+    assert_eq!(restored.shop.next_u64(), run_rng.shop.next_u64());
+    assert_eq!(restored.coins.next_u64(), run_rng.coins.next_u64());
+}
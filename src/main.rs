@@ -4,18 +4,26 @@ mod gadgets;
 mod game_ui;
 mod gameplay;
 mod general;
+mod replay;
+mod save;
+mod simulation;
 
 use crate::experiments::ExperimentsPlugin;
+use crate::gadgets::balance::{BalanceConfig, BalanceConfigLoader};
 use crate::gadgets::resources::GameResources;
 use crate::game_ui::GameUiPlugin;
-use crate::gameplay::GameplayPlugin;
+use crate::gameplay::{GameplayPlugin, GameplayVisualsPlugin};
 use crate::gameplay::components::*;
+use crate::gameplay::resources::RunRng;
 use crate::general::GeneralPlugin;
 use crate::general::components::*;
+use crate::replay::ReplayPlugin;
+use crate::save::SavePlugin;
+use crate::simulation::resources::SimulationConfig;
 use avian2d::PhysicsPlugins;
 use avian2d::math::Vector;
 use avian2d::prelude::*;
-use bevy::asset::AssetMetaCheck;
+use bevy::asset::{AssetMetaCheck, LoadState};
 use bevy::prelude::*;
 use bevy_easings::EasingsPlugin;
 use bevy_inspector_egui::bevy_egui::EguiPlugin;
@@ -26,6 +34,10 @@ use gameplay::game_states::*;
 use rand::Rng;
 
 fn main() -> AppExit {
+    if std::env::args().any(|arg| arg == "--headless") {
+        return simulation::run_headless(SimulationConfig::from_args(std::env::args()));
+    }
+
     let mut app = App::new();
     app.add_plugins(
         DefaultPlugins
@@ -53,18 +65,25 @@ fn main() -> AppExit {
     app.add_plugins(SimpleSubsecondPlugin::default());
     app.add_plugins(EntropyPlugin::<WyRand>::default());
     app.add_plugins(Shape2dPlugin::default());
-    app.add_plugins(PhysicsPlugins::default());
+    // physics only advances in fixed steps, a replay has to see the same steps as the recorded run
+    app.insert_resource(Time::<Fixed>::from_hz(64.0));
+    app.add_plugins(PhysicsPlugins::new(FixedPostUpdate));
     app.add_plugins(PhysicsPickingPlugin::default());
     // app.add_plugins(PhysicsDebugPlugin::default());
     // app.add_plugins(WorldInspectorPlugin::new());
     app.add_plugins(EasingsPlugin::default());
     app.insert_resource(Gravity(Vector::NEG_Y * 9.81 * 100.0));
     app.insert_resource(GameResources::default());
+    app.init_asset::<BalanceConfig>();
+    app.init_asset_loader::<BalanceConfigLoader>();
 
     app.add_plugins(GeneralPlugin);
     app.add_plugins(GameplayPlugin);
+    app.add_plugins(GameplayVisualsPlugin);
     app.add_plugins(GameUiPlugin);
     app.add_plugins(ExperimentsPlugin);
+    app.add_plugins(SavePlugin);
+    app.add_plugins(ReplayPlugin);
 
     //game states
     app.insert_state(AppState::Loading);
@@ -72,6 +91,10 @@ fn main() -> AppExit {
     app.add_sub_state::<MenuState>();
 
     app.add_systems(OnEnter(AppState::Loading), load_assets);
+    app.add_systems(
+        Update,
+        wait_for_balance_config.run_if(in_state(AppState::Loading)),
+    );
     app.add_systems(OnEnter(AppState::Startup), startup_setup);
     app.add_systems(OnEnter(AppState::InGame), main_game_setup);
 
@@ -86,10 +109,25 @@ struct DestroyOnHot;
 pub fn load_assets(
     asset_server: Res<AssetServer>,
     mut gadget_resource: ResMut<GameResources>,
-    mut next_state: ResMut<NextState<AppState>>,
 ) {
     gadget_resource.setup(&asset_server);
-    next_state.set(AppState::Startup);
+}
+
+pub fn wait_for_balance_config(
+    asset_server: Res<AssetServer>,
+    balance_configs: Res<Assets<BalanceConfig>>,
+    mut gadget_resource: ResMut<GameResources>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let handle = gadget_resource.balance_config.clone();
+    if let Some(config) = balance_configs.get(&handle) {
+        gadget_resource.apply_balance_config(config);
+        next_state.set(AppState::Startup);
+    } else if let Some(LoadState::Failed(error)) = asset_server.get_load_state(&handle) {
+        error!("{error}, using the balance config the game was built with");
+        gadget_resource.apply_balance_config(&BalanceConfig::fallback());
+        next_state.set(AppState::Startup);
+    }
 }
 
 pub fn startup_setup(mut commands: Commands, mut next_state: ResMut<NextState<AppState>>) {
@@ -101,7 +139,7 @@ pub fn startup_setup(mut commands: Commands, mut next_state: ResMut<NextState<Ap
 pub fn main_game_setup(
     mut commands: Commands,
     previous_setup: Query<Entity, With<DestroyOnHot>>,
-    mut rng: GlobalEntropy<WyRand>,
+    mut run_rng: ResMut<RunRng>,
     game_resources: Res<GameResources>,
 ) {
     for entity in previous_setup.iter() {
@@ -110,10 +148,10 @@ pub fn main_game_setup(
 
     let x_position = game_resources.play_area.x - 0.0;
     let y_min_max = game_resources.play_area.y - 0.0;
-    let y_position = rng.random_range(-y_min_max..=y_min_max);
+    let y_position = run_rng.cannon.random_range(-y_min_max..=y_min_max);
 
     let base_angle = 90.0;
-    let jitter = rng.random_range(-60.0..=60.0);
+    let jitter = run_rng.cannon.random_range(-60.0..=60.0);
     // let jitter = 0.0;
 
     let angle = base_angle + jitter;
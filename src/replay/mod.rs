@@ -0,0 +1,48 @@
+use crate::gameplay::game_states::LevelState;
+use crate::replay::resources::*;
+use crate::replay::systems::*;
+use bevy::prelude::*;
+
+pub mod resources;
+mod systems;
+
+/// Inputs of the bouncing phase are recorded or replayed in this set, before the gameplay reacts
+/// to them on the same physics tick.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ReplayInputSet;
+
+pub struct ReplayPlugin;
+
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReplayRecorder>();
+        app.init_resource::<BouncingTicks>();
+
+        app.add_systems(OnEnter(LevelState::LevelStart), start_recording_system);
+        app.add_systems(OnEnter(LevelState::WidgetSelection), store_replay_system);
+        app.add_systems(OnEnter(LevelState::GameOver), store_replay_system);
+        app.add_systems(Last, store_replay_on_exit_system);
+
+        app.add_systems(
+            Update,
+            replay_playback_system.run_if(resource_exists::<ReplayPlayback>),
+        );
+        app.add_systems(OnEnter(LevelState::BallBouncing), reset_bouncing_ticks);
+        app.add_systems(
+            FixedUpdate,
+            (
+                replay_fixed_playback_system.run_if(resource_exists::<ReplayPlayback>),
+                record_fixed_input_system.run_if(is_recording),
+            )
+                .chain()
+                .in_set(ReplayInputSet)
+                .run_if(in_state(LevelState::BallBouncing)),
+        );
+        app.add_systems(
+            FixedLast,
+            count_bouncing_ticks_system.run_if(in_state(LevelState::BallBouncing)),
+        );
+
+        app.add_observer(record_replay_action);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::cards::components::ShopCardType;
+use crate::gameplay::resources::RunModifiers;
+use crate::save::storage::{self, SaveError};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+const REPLAY_KEY: &str = "boingle_replay";
+
+/// Every decision the player can make during a run. Together with the seed they are enough to
+/// play the run again.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReplayAction {
+    SelectCard(ShopCardType),
+    PlaceGadget { translation: [f32; 3], rotation: f32 },
+    PickPlacedGadget { translation: [f32; 3] },
+    SkipPickingGadget,
+    FireCannon { power: f32, angle: f32 },
+    /// `tick` counts the physics ticks since the ball was fired
+    Flip { tick: u32, is_flipping: bool },
+    ClearBalls { tick: u32 },
+    ContinueRound,
+    BuyCard(ShopCardType),
+    RemoveCard(ShopCardType),
+    UpgradeCard(ShopCardType),
+    RerollShop,
+    LeaveShop,
+}
+
+impl ReplayAction {
+    /// Actions taken while the ball is bouncing have to happen on the same physics tick.
+    pub fn tick(&self) -> Option<u32> {
+        match self {
+            ReplayAction::Flip { tick, .. } | ReplayAction::ClearBalls { tick } => Some(*tick),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayLog {
+    pub seed: String,
+    #[serde(default)]
+    pub modifiers: RunModifiers,
+    pub actions: Vec<ReplayAction>,
+}
+
+impl ReplayLog {
+    pub fn new(seed: String, modifiers: RunModifiers) -> Self {
+        Self {
+            seed,
+            modifiers,
+            actions: Vec::new(),
+        }
+    }
+
+    pub fn load() -> Result<Option<Self>, SaveError> {
+        let Some(text) = storage::read_text(REPLAY_KEY)? else {
+            return Ok(None);
+        };
+        Ok(Some(ron::from_str(&text)?))
+    }
+
+    pub fn store(&self) -> Result<(), SaveError> {
+        let text = ron::to_string(self)?;
+        storage::write_text(REPLAY_KEY, &text)
+    }
+}
+
+/// Triggered by the gameplay code for every player decision.
+#[derive(Event, Debug)]
+pub struct RecordReplayAction(pub ReplayAction);
+
+/// Log of the current run, `None` while a replay is played back or after a run was continued.
+#[derive(Resource, Debug, Default)]
+pub struct ReplayRecorder(pub Option<ReplayLog>);
+
+/// Present while a replay is played back, live input is ignored in that time.
+#[derive(Resource, Debug)]
+pub struct ReplayPlayback {
+    actions: VecDeque<ReplayAction>,
+}
+
+impl ReplayPlayback {
+    pub fn new(actions: Vec<ReplayAction>) -> Self {
+        Self {
+            actions: actions.into(),
+        }
+    }
+
+    pub fn next_action(&self) -> Option<ReplayAction> {
+        self.actions.front().copied()
+    }
+
+    pub fn advance(&mut self) {
+        self.actions.pop_front();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
+
+/// Physics ticks since the ball was fired.
+#[derive(Resource, Debug, Default)]
+pub struct BouncingTicks(pub u32);
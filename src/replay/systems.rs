@@ -0,0 +1,214 @@
+use crate::cards::components::{OnClickOnShopCard, ShopCard};
+use crate::gadgets::components::PlayerPlacedGadget;
+use crate::gameplay::components::Player;
+use crate::gameplay::events::*;
+use crate::gameplay::game_states::LevelState;
+use crate::gameplay::resources::{FlipperInput, PendingBallClear, RunModifiers, RunSeed};
+use crate::replay::resources::*;
+use crate::save::resources::PendingRunRestore;
+use avian2d::prelude::*;
+use bevy::prelude::*;
+
+pub fn start_recording_system(
+    mut commands: Commands,
+    run_seed: Res<RunSeed>,
+    run_modifiers: Res<RunModifiers>,
+    pending_restore: Option<Res<PendingRunRestore>>,
+    playback: Option<Res<ReplayPlayback>>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    if let Some(playback) = playback {
+        if !playback.is_finished() {
+            recorder.0 = None;
+            return;
+        }
+        commands.remove_resource::<ReplayPlayback>();
+    }
+    // a continued run can not be replayed, the actions before the save are missing
+    if pending_restore.is_some() {
+        recorder.0 = None;
+        return;
+    }
+    recorder.0 = Some(ReplayLog::new(run_seed.text.clone(), run_modifiers.clone()));
+}
+
+pub fn record_replay_action(
+    trigger: Trigger<RecordReplayAction>,
+    mut recorder: ResMut<ReplayRecorder>,
+) {
+    if let Some(replay_log) = recorder.0.as_mut() {
+        replay_log.actions.push(trigger.0);
+    }
+}
+
+pub fn is_recording(recorder: Res<ReplayRecorder>) -> bool {
+    recorder.0.is_some()
+}
+
+pub fn store_replay_system(recorder: Res<ReplayRecorder>) {
+    let Some(replay_log) = &recorder.0 else {
+        return;
+    };
+    if let Err(error) = replay_log.store() {
+        warn!("{error}");
+    }
+}
+
+/// Keeps the actions of an unfinished round, bugs are usually reported right after quitting.
+pub fn store_replay_on_exit_system(
+    mut exit_events: EventReader<AppExit>,
+    recorder: Res<ReplayRecorder>,
+) {
+    if exit_events.read().count() == 0 {
+        return;
+    }
+    store_replay_system(recorder);
+}
+
+pub fn replay_playback_system(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    state: Res<State<LevelState>>,
+    player: Single<&Player>,
+    q_hand_card: Query<(), With<OnGadgetCardSelected>>,
+    q_shop_card: Query<(Entity, &ShopCard), (With<OnClickOnShopCard>, With<Collider>)>,
+    q_placed: Query<(Entity, &Transform), With<PlayerPlacedGadget>>,
+) {
+    let Some(action) = playback.next_action() else {
+        info!("The replay is over, the run continues with live input");
+        commands.remove_resource::<ReplayPlayback>();
+        return;
+    };
+
+    let is_applied = match (action, state.get()) {
+        (ReplayAction::SelectCard(card), LevelState::WidgetSelection) if !q_hand_card.is_empty() => {
+            commands.trigger(OnGadgetCardSelected::new(card));
+            true
+        }
+        (ReplayAction::PlaceGadget { translation, rotation }, LevelState::PlaceWidget)
+            if player.current_widget.is_some() =>
+        {
+            commands.trigger(RequestToPlaceGadget::new(
+                Vec3::from_array(translation),
+                rotation,
+            ));
+            true
+        }
+        (
+            ReplayAction::PickPlacedGadget { translation },
+            LevelState::RecycleGadget | LevelState::RearrangeGadget,
+        ) => {
+            let translation = Vec3::from_array(translation);
+            let gadget = q_placed
+                .iter()
+                .min_by(|(_, a), (_, b)| {
+                    a.translation
+                        .distance_squared(translation)
+                        .total_cmp(&b.translation.distance_squared(translation))
+                })
+                .map(|(entity, _)| entity);
+            commands.trigger(RequestToPickPlacedGadget { gadget });
+            true
+        }
+        (
+            ReplayAction::SkipPickingGadget,
+            LevelState::RecycleGadget | LevelState::RearrangeGadget,
+        ) => {
+            commands.trigger(RequestToPickPlacedGadget { gadget: None });
+            true
+        }
+        (ReplayAction::FireCannon { power, angle }, LevelState::ShootBall) => {
+            commands.trigger(RequestToFireCannon { power, angle });
+            true
+        }
+        (ReplayAction::ContinueRound, LevelState::EndOfRound) => {
+            commands.trigger(RequestToContinueRound);
+            true
+        }
+        (ReplayAction::BuyCard(card), LevelState::Shop) => {
+            match q_shop_card
+                .iter()
+                .find(|(_, shop_card)| shop_card.card_type == card)
+            {
+                Some((entity, _)) => {
+                    commands.trigger_targets(OnClickOnShopCard, entity);
+                    true
+                }
+                None => false,
+            }
+        }
+        (ReplayAction::RemoveCard(card), LevelState::Shop) => {
+            commands.trigger(RequestShopService {
+                service: ShopService::RemoveCard,
+                card,
+            });
+            true
+        }
+        (ReplayAction::UpgradeCard(card), LevelState::Shop) => {
+            commands.trigger(RequestShopService {
+                service: ShopService::UpgradeCard,
+                card,
+            });
+            true
+        }
+        (ReplayAction::RerollShop, LevelState::Shop) => {
+            commands.trigger(RequestToRerollShop);
+            true
+        }
+        (ReplayAction::LeaveShop, LevelState::Shop) => {
+            commands.trigger(RequestToLeaveShop);
+            true
+        }
+        _ => false,
+    };
+
+    if is_applied {
+        playback.advance();
+    }
+}
+
+/// Applies the actions taken while the ball is bouncing on the tick they were recorded on.
+pub fn replay_fixed_playback_system(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    ticks: Res<BouncingTicks>,
+    mut flipper_input: ResMut<FlipperInput>,
+) {
+    while let Some(action) = playback.next_action() {
+        match action {
+            ReplayAction::Flip { tick, is_flipping } if tick <= ticks.0 => {
+                flipper_input.set_if_neq(FlipperInput { is_flipping });
+            }
+            ReplayAction::ClearBalls { tick } if tick <= ticks.0 => {
+                commands.insert_resource(PendingBallClear);
+            }
+            _ => return,
+        }
+        playback.advance();
+    }
+}
+
+pub fn record_fixed_input_system(
+    mut commands: Commands,
+    ticks: Res<BouncingTicks>,
+    flipper_input: Res<FlipperInput>,
+    pending_ball_clear: Option<Res<PendingBallClear>>,
+) {
+    if flipper_input.is_changed() {
+        commands.trigger(RecordReplayAction(ReplayAction::Flip {
+            tick: ticks.0,
+            is_flipping: flipper_input.is_flipping,
+        }));
+    }
+    if pending_ball_clear.is_some() {
+        commands.trigger(RecordReplayAction(ReplayAction::ClearBalls { tick: ticks.0 }));
+    }
+}
+
+pub fn reset_bouncing_ticks(mut ticks: ResMut<BouncingTicks>) {
+    ticks.0 = 0;
+}
+
+pub fn count_bouncing_ticks_system(mut ticks: ResMut<BouncingTicks>) {
+    ticks.0 += 1;
+}
@@ -0,0 +1,18 @@
+use crate::gameplay::game_states::LevelState;
+use crate::save::systems::*;
+use bevy::prelude::*;
+
+pub mod resources;
+pub mod storage;
+mod systems;
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(LevelState::WidgetSelection), save_run_system);
+        app.add_systems(OnEnter(LevelState::GameOver), delete_save_system);
+        app.add_systems(OnEnter(LevelState::GameOver), record_daily_score_system);
+        app.add_systems(Last, save_on_exit_system);
+    }
+}
@@ -0,0 +1,215 @@
+use crate::cards::components::ShopCardType;
+use crate::gadgets::components::*;
+use crate::gadgets::resources::GameResources;
+use crate::gameplay::components::Player;
+use crate::gameplay::resources::{RunModifiers, RunRng};
+use crate::save::storage::{self, SaveError};
+use bevy::prelude::*;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const SAVE_KEY: &str = "boingle_save";
+const DAILY_SCORES_KEY: &str = "boingle_daily_scores";
+
+/// Inserted by "Continue" in the main menu, consumed when the level starts.
+#[derive(Resource, Debug)]
+pub struct PendingRunRestore(pub SaveGame);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    #[serde(default)]
+    pub seed: Option<String>,
+    #[serde(default)]
+    pub modifiers: RunModifiers,
+    /// position of every RNG stream, saves without it start the streams over from the seed
+    #[serde(default)]
+    pub rng: Option<RunRng>,
+    pub player: SavedPlayer,
+    pub gadgets: Vec<SavedGadget>,
+    pub collectibles: Vec<SavedCollectible>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub current_hand: Vec<ShopCardType>,
+    pub widget_deck: Vec<ShopCardType>,
+    pub discard_pile: Vec<ShopCardType>,
+    pub points: usize,
+    pub points_last_round: usize,
+    pub coins: usize,
+    pub balls_left: usize,
+    pub current_level: usize,
+    pub point_for_next_level: usize,
+    #[serde(default)]
+    pub card_levels: BTreeMap<ShopCardType, usize>,
+    #[serde(default)]
+    pub shops_without_rare: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedGadget {
+    pub card: ShopCardType,
+    pub translation: [f32; 3],
+    pub rotation: f32,
+    pub activations_left: Option<usize>,
+    /// index of the other end of a portal pair
+    #[serde(default)]
+    pub linked_gadget: Option<usize>,
+    #[serde(default)]
+    pub level: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedCollectible {
+    pub collectible: CollectibleType,
+    pub translation: [f32; 3],
+    pub scale: [f32; 3],
+    pub remaining_rounds: usize,
+}
+
+impl SavedPlayer {
+    pub fn from_player(player: &Player) -> Self {
+        Self {
+            current_hand: player.current_hand.clone(),
+            widget_deck: player.widget_deck.clone(),
+            discard_pile: player.discard_pile.clone(),
+            points: player.points,
+            points_last_round: player.points_last_round,
+            coins: player.coins,
+            balls_left: player.balls_left,
+            current_level: player.current_level,
+            point_for_next_level: player.point_for_next_level,
+            card_levels: player.card_levels.clone(),
+            shops_without_rare: player.shops_without_rare,
+        }
+    }
+
+    pub fn apply_to(&self, player: &mut Player) {
+        player.current_widget = None;
+        player.current_hand = self.current_hand.clone();
+        player.widget_deck = self.widget_deck.clone();
+        player.discard_pile = self.discard_pile.clone();
+        player.points = self.points;
+        player.points_this_round = 0;
+        player.points_last_round = self.points_last_round;
+        player.coins = self.coins;
+        player.balls_left = self.balls_left;
+        player.current_level = self.current_level;
+        player.point_for_next_level = self.point_for_next_level;
+        player.card_levels = self.card_levels.clone();
+        player.shops_without_rare = self.shops_without_rare;
+    }
+}
+
+impl SaveGame {
+    pub fn load() -> Result<Option<Self>, SaveError> {
+        let Some(text) = storage::read_text(SAVE_KEY)? else {
+            return Ok(None);
+        };
+        Ok(Some(ron::from_str(&text)?))
+    }
+
+    pub fn store(&self) -> Result<(), SaveError> {
+        let text = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        storage::write_text(SAVE_KEY, &text)
+    }
+
+    pub fn delete() -> Result<(), SaveError> {
+        storage::remove(SAVE_KEY)
+    }
+
+    /// Spawns the saved board and puts the saved values back into the player.
+    pub fn restore(
+        &self,
+        commands: &mut Commands,
+        player: &mut Player,
+        game_resources: &GameResources,
+    ) {
+        self.player.apply_to(player);
+
+        let mut gadget_entities = Vec::with_capacity(self.gadgets.len());
+        for saved_gadget in self.gadgets.iter() {
+            let card = saved_gadget.card;
+            let Some(entity) = card.spawn_gadget(commands, game_resources, saved_gadget.level)
+            else {
+                warn!("{:?} can not be placed as a gadget", saved_gadget.card);
+                gadget_entities.push(None);
+                continue;
+            };
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.insert((
+                Transform::from_translation(Vec3::from_array(saved_gadget.translation))
+                    .with_rotation(Quat::from_rotation_z(saved_gadget.rotation)),
+                PlayerPlacedGadget,
+                Pickable::IGNORE,
+            ));
+            if let Some(activations_left) = saved_gadget.activations_left {
+                entity_commands
+                    .entry::<Gadget>()
+                    .and_modify(move |mut gadget| {
+                        gadget.activations_left = activations_left.min(gadget.activations_per_round);
+                    });
+            }
+            gadget_entities.push(Some(entity));
+        }
+
+        for (saved_gadget, entity) in self.gadgets.iter().zip(gadget_entities.iter()) {
+            let (Some(linked_gadget), Some(entity)) = (saved_gadget.linked_gadget, entity) else {
+                continue;
+            };
+            if let Some(Some(linked_entity)) = gadget_entities.get(linked_gadget) {
+                commands
+                    .entity(*entity)
+                    .insert(Portal::linked_to(*linked_entity));
+            }
+        }
+
+        for saved_collectible in self.collectibles.iter() {
+            let transform = Transform::from_translation(Vec3::from_array(
+                saved_collectible.translation,
+            ))
+            .with_scale(Vec3::from_array(saved_collectible.scale));
+            match saved_collectible.collectible {
+                CollectibleType::CoinType => {
+                    commands.spawn((
+                        CollectibleType::coin_bundle(),
+                        transform,
+                        RemainingRounds(saved_collectible.remaining_rounds),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Best points per daily challenge, keyed by the date of the challenge.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DailyScores(pub BTreeMap<String, usize>);
+
+impl DailyScores {
+    pub fn load() -> Result<Self, SaveError> {
+        let Some(text) = storage::read_text(DAILY_SCORES_KEY)? else {
+            return Ok(Self::default());
+        };
+        Ok(ron::from_str(&text)?)
+    }
+
+    pub fn store(&self) -> Result<(), SaveError> {
+        let text = ron::ser::to_string_pretty(self, PrettyConfig::default())?;
+        storage::write_text(DAILY_SCORES_KEY, &text)
+    }
+
+    pub fn best(&self, date: &str) -> Option<usize> {
+        self.0.get(date).copied()
+    }
+
+    /// Returns true if the points are a new best for that day.
+    pub fn record(&mut self, date: &str, points: usize) -> bool {
+        if self.best(date).is_some_and(|best| best >= points) {
+            return false;
+        }
+        self.0.insert(date.to_string(), points);
+        true
+    }
+}
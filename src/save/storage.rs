@@ -0,0 +1,70 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("could not access the save file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not write the save: {0}")]
+    Serialize(#[from] ron::Error),
+    #[error("could not read the save: {0}")]
+    Deserialize(#[from] ron::error::SpannedError),
+    #[cfg(target_arch = "wasm32")]
+    #[error("local storage is not available")]
+    StorageUnavailable,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_path(key: &str) -> std::path::PathBuf {
+    std::path::PathBuf::from(format!("{key}.ron"))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn write_text(key: &str, text: &str) -> Result<(), SaveError> {
+    std::fs::write(save_path(key), text)?;
+    Ok(())
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn read_text(key: &str) -> Result<Option<String>, SaveError> {
+    match std::fs::read_to_string(save_path(key)) {
+        Ok(text) => Ok(Some(text)),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub fn remove(key: &str) -> Result<(), SaveError> {
+    match std::fs::remove_file(save_path(key)) {
+        Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, SaveError> {
+    web_sys::window()
+        .and_then(|window| window.local_storage().ok().flatten())
+        .ok_or(SaveError::StorageUnavailable)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn write_text(key: &str, text: &str) -> Result<(), SaveError> {
+    local_storage()?
+        .set_item(key, text)
+        .map_err(|_| SaveError::StorageUnavailable)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn read_text(key: &str) -> Result<Option<String>, SaveError> {
+    local_storage()?
+        .get_item(key)
+        .map_err(|_| SaveError::StorageUnavailable)
+}
+
+#[cfg(target_arch = "wasm32")]
+pub fn remove(key: &str) -> Result<(), SaveError> {
+    local_storage()?
+        .remove_item(key)
+        .map_err(|_| SaveError::StorageUnavailable)
+}
@@ -0,0 +1,157 @@
+use crate::gadgets::components::*;
+use crate::gameplay::components::Player;
+use crate::gameplay::game_states::LevelState;
+use crate::gameplay::resources::{RunModifiers, RunRng, RunSeed};
+use crate::save::resources::*;
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+type SavedGadgetQuery<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static SpawnedFromCard,
+        &'static Transform,
+        Option<&'static Gadget>,
+        Option<&'static Portal>,
+        Option<&'static UpgradeLevel>,
+    ),
+    With<PlayerPlacedGadget>,
+>;
+
+type SavedCollectibleQuery<'w, 's> =
+    Query<'w, 's, (&'static CollectibleType, &'static Transform, &'static RemainingRounds)>;
+
+fn capture_run(
+    player: &Player,
+    run_seed: Option<&RunSeed>,
+    run_rng: Option<&RunRng>,
+    run_modifiers: &RunModifiers,
+    q_gadget: &SavedGadgetQuery,
+    q_collectible: &SavedCollectibleQuery,
+) -> SaveGame {
+    let gadget_indices: HashMap<Entity, usize> = q_gadget
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, ..))| (entity, index))
+        .collect();
+
+    let gadgets = q_gadget
+        .iter()
+        .map(
+            |(_, spawned_from_card, transform, gadget, portal, upgrade_level)| SavedGadget {
+                card: spawned_from_card.0,
+                translation: transform.translation.to_array(),
+                rotation: transform.rotation.to_euler(EulerRot::XYZ).2,
+                activations_left: gadget.map(|gadget| gadget.activations_left),
+                linked_gadget: portal
+                    .and_then(|portal| portal.linked_portal)
+                    .and_then(|linked| gadget_indices.get(&linked).copied()),
+                level: upgrade_level.map_or(0, |upgrade_level| upgrade_level.0),
+            },
+        )
+        .collect();
+
+    let collectibles = q_collectible
+        .iter()
+        .map(
+            |(collectible, transform, remaining_rounds)| SavedCollectible {
+                collectible: collectible.clone(),
+                translation: transform.translation.to_array(),
+                scale: transform.scale.to_array(),
+                remaining_rounds: remaining_rounds.0,
+            },
+        )
+        .collect();
+
+    SaveGame {
+        seed: run_seed.map(|run_seed| run_seed.text.clone()),
+        modifiers: run_modifiers.clone(),
+        rng: run_rng.cloned(),
+        player: SavedPlayer::from_player(player),
+        gadgets,
+        collectibles,
+    }
+}
+
+pub fn save_run_system(
+    player: Single<&Player>,
+    run_seed: Option<Res<RunSeed>>,
+    run_rng: Option<Res<RunRng>>,
+    run_modifiers: Res<RunModifiers>,
+    q_gadget: SavedGadgetQuery,
+    q_collectible: SavedCollectibleQuery,
+) {
+    let save_game = capture_run(
+        &player,
+        run_seed.as_deref(),
+        run_rng.as_deref(),
+        &run_modifiers,
+        &q_gadget,
+        &q_collectible,
+    );
+    if let Err(error) = save_game.store() {
+        warn!("{error}");
+    }
+}
+
+pub fn save_on_exit_system(
+    mut exit_events: EventReader<AppExit>,
+    state: Option<Res<State<LevelState>>>,
+    player: Single<&Player>,
+    run_seed: Option<Res<RunSeed>>,
+    run_rng: Option<Res<RunRng>>,
+    run_modifiers: Res<RunModifiers>,
+    q_gadget: SavedGadgetQuery,
+    q_collectible: SavedCollectibleQuery,
+) {
+    if exit_events.read().count() == 0 {
+        return;
+    }
+    // mid round states can't be resumed exactly, the snapshot taken when the
+    // round started is kept for them instead
+    let Some(state) = state else {
+        return;
+    };
+    if *state.get() != LevelState::WidgetSelection {
+        return;
+    }
+    let save_game = capture_run(
+        &player,
+        run_seed.as_deref(),
+        run_rng.as_deref(),
+        &run_modifiers,
+        &q_gadget,
+        &q_collectible,
+    );
+    if let Err(error) = save_game.store() {
+        warn!("{error}");
+    }
+}
+
+pub fn delete_save_system() {
+    if let Err(error) = SaveGame::delete() {
+        warn!("{error}");
+    }
+}
+
+pub fn record_daily_score_system(player: Single<&Player>, run_modifiers: Res<RunModifiers>) {
+    let Some(date) = &run_modifiers.daily else {
+        return;
+    };
+    let mut daily_scores = match DailyScores::load() {
+        Ok(daily_scores) => daily_scores,
+        Err(error) => {
+            warn!("{error}");
+            return;
+        }
+    };
+    if !daily_scores.record(date, player.points) {
+        return;
+    }
+    info!("New best daily score for {}: {}", date, player.points);
+    if let Err(error) = daily_scores.store() {
+        warn!("{error}");
+    }
+}
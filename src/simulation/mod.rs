@@ -0,0 +1,113 @@
+use crate::gadgets::balance::{BalanceConfig, BalanceConfigLoader};
+use crate::gadgets::resources::GameResources;
+use crate::gameplay::GameplayPlugin;
+use crate::gameplay::game_states::{AppState, LevelState};
+use crate::general::resources::GameCursor;
+use crate::simulation::resources::*;
+use crate::simulation::systems::*;
+use avian2d::math::Vector;
+use avian2d::prelude::*;
+use bevy::app::ScheduleRunnerPlugin;
+use bevy::input::InputPlugin;
+use bevy::log::LogPlugin;
+use bevy::prelude::*;
+use bevy::state::app::StatesPlugin;
+use bevy::time::TimeUpdateStrategy;
+use bevy_easings::EasingsPlugin;
+use bevy_rand::prelude::*;
+use bevy_simple_subsecond_system::prelude::*;
+use std::time::Duration;
+
+pub mod resources;
+mod systems;
+
+/// Plays the game without a window, a random policy makes all the decisions. Used to collect
+/// balance stats over many runs.
+pub fn run_headless(config: SimulationConfig) -> AppExit {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::ZERO)));
+    app.add_plugins((
+        LogPlugin::default(),
+        StatesPlugin,
+        AssetPlugin::default(),
+        TransformPlugin,
+        InputPlugin,
+    ));
+    // every update is exactly one physics tick, as many of them as the machine can do
+    app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1.0 / 64.0,
+    )));
+    app.insert_resource(Time::<Fixed>::from_hz(64.0));
+    app.add_plugins(SimpleSubsecondPlugin::default());
+    app.add_plugins(EntropyPlugin::<WyRand>::default());
+    app.add_plugins(PhysicsPlugins::new(FixedPostUpdate));
+    app.add_plugins(EasingsPlugin::default());
+    app.insert_resource(Gravity(Vector::NEG_Y * 9.81 * 100.0));
+    app.insert_resource(GameResources::default());
+    app.init_asset::<BalanceConfig>();
+    app.init_asset_loader::<BalanceConfigLoader>();
+    app.init_resource::<GameCursor>();
+
+    app.insert_resource(config);
+    app.add_plugins(GameplayPlugin);
+    app.add_plugins(SimulationPlugin);
+
+    app.insert_state(AppState::Loading);
+    app.add_sub_state::<LevelState>();
+
+    app.run()
+}
+
+pub struct SimulationPlugin;
+
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SimulationStats>();
+        app.add_systems(Startup, seed_policy_rng);
+
+        app.add_systems(OnEnter(AppState::Loading), load_headless_assets);
+        app.add_systems(
+            Update,
+            crate::wait_for_balance_config.run_if(in_state(AppState::Loading)),
+        );
+        app.add_systems(OnEnter(AppState::Startup), start_next_run_system);
+        app.add_systems(OnEnter(AppState::InGame), crate::main_game_setup);
+
+        app.add_systems(OnEnter(LevelState::WidgetSelection), fill_hand_system);
+        app.add_systems(
+            Update,
+            pick_card_system.run_if(in_state(LevelState::WidgetSelection)),
+        );
+        app.add_systems(
+            Update,
+            place_gadget_system.run_if(in_state(LevelState::PlaceWidget)),
+        );
+        app.add_systems(
+            Update,
+            pick_placed_gadget_system.run_if(
+                in_state(LevelState::RecycleGadget).or(in_state(LevelState::RearrangeGadget)),
+            ),
+        );
+        app.add_systems(
+            Update,
+            fire_cannon_system.run_if(in_state(LevelState::ShootBall)),
+        );
+        app.add_systems(OnEnter(LevelState::BallBouncing), start_bouncing_timeout);
+        app.add_systems(
+            FixedUpdate,
+            bouncing_timeout_system.run_if(in_state(LevelState::BallBouncing)),
+        );
+        app.add_systems(
+            Update,
+            continue_round_system.run_if(in_state(LevelState::EndOfRound)),
+        );
+        app.add_systems(OnEnter(LevelState::Shop), stock_shop_system);
+        app.add_systems(Update, shop_system.run_if(in_state(LevelState::Shop)));
+
+        app.add_systems(OnExit(LevelState::EndOfRound), record_round_system);
+        app.add_systems(OnEnter(LevelState::GameOver), record_run_system);
+
+        app.add_observer(count_collected_coins);
+        app.add_observer(restock_shop_system);
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cards::components::ShopCardType;
+use bevy::prelude::*;
+use bevy_rand::prelude::{Entropy, WyRand};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Settings of a headless run, read from the command line:
+/// `--headless [--runs N] [--seed TEXT] [--out DIR]`.
+#[derive(Resource, Debug, Clone)]
+pub struct SimulationConfig {
+    pub runs: usize,
+    /// every run gets its own seed derived from this one
+    pub seed: String,
+    pub output_dir: PathBuf,
+    /// balls still bouncing after this long are removed, like a player clicking the cannon
+    pub max_bouncing_time: Duration,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            runs: 100,
+            seed: "SIMULATE".to_string(),
+            output_dir: PathBuf::from("simulation"),
+            max_bouncing_time: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SimulationConfig {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Self {
+        let mut config = Self::default();
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--runs" => match args.next().map(|runs| runs.parse()) {
+                    Some(Ok(runs)) => config.runs = runs,
+                    _ => eprintln!("--runs expects a number, keeping {}", config.runs),
+                },
+                "--seed" => {
+                    if let Some(seed) = args.next() {
+                        config.seed = seed;
+                    }
+                }
+                "--out" => {
+                    if let Some(output_dir) = args.next() {
+                        config.output_dir = PathBuf::from(output_dir);
+                    }
+                }
+                _ => {}
+            }
+        }
+        config
+    }
+}
+
+/// Decisions of the random policy, kept apart from the run streams so the runs stay the same as
+/// with a player making other choices.
+#[derive(Resource)]
+pub struct PolicyRng(pub Entropy<WyRand>);
+
+/// Cards offered by the shop of the current visit.
+#[derive(Resource, Debug, Default)]
+pub struct ShopOffers(pub Vec<ShopCardType>);
+
+#[derive(Resource, Debug)]
+pub struct BouncingTimeout(pub Timer);
+
+#[derive(Debug, Clone)]
+pub struct RoundStats {
+    pub run: usize,
+    pub round: usize,
+    pub level: usize,
+    pub points: usize,
+    pub total_points: usize,
+    pub coins: usize,
+    pub balls_left: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RunStats {
+    pub run: usize,
+    pub seed: String,
+    pub level_reached: usize,
+    pub points: usize,
+    pub rounds: usize,
+    /// coins earned per card, coins placed at the start of a level are listed as `Level`
+    pub coins_per_source: BTreeMap<String, usize>,
+}
+
+#[derive(Resource, Debug, Default)]
+pub struct SimulationStats {
+    pub current_run: usize,
+    pub current_round: usize,
+    pub current_coins: BTreeMap<String, usize>,
+    pub rounds: Vec<RoundStats>,
+    pub runs: Vec<RunStats>,
+}
+
+impl SimulationStats {
+    pub fn add_coins(&mut self, source: Option<ShopCardType>, amount: usize) {
+        let source = source.map_or("Level".to_string(), |card| format!("{:?}", card));
+        *self.current_coins.entry(source).or_default() += amount;
+    }
+
+    pub fn rounds_csv(&self) -> String {
+        let mut csv = "run,round,level,points,total_points,coins,balls_left\n".to_string();
+        for round in self.rounds.iter() {
+            csv += &format!(
+                "{},{},{},{},{},{},{}\n",
+                round.run,
+                round.round,
+                round.level,
+                round.points,
+                round.total_points,
+                round.coins,
+                round.balls_left
+            );
+        }
+        csv
+    }
+
+    pub fn runs_csv(&self) -> String {
+        let mut csv = "run,seed,level_reached,points,rounds\n".to_string();
+        for run in self.runs.iter() {
+            csv += &format!(
+                "{},{},{},{},{}\n",
+                run.run, run.seed, run.level_reached, run.points, run.rounds
+            );
+        }
+        csv
+    }
+
+    pub fn coins_csv(&self) -> String {
+        let mut csv = "run,source,coins\n".to_string();
+        for run in self.runs.iter() {
+            for (source, coins) in run.coins_per_source.iter() {
+                csv += &format!("{},{},{}\n", run.run, source, coins);
+            }
+        }
+        csv
+    }
+}
@@ -0,0 +1,311 @@
+use crate::gadgets::components::*;
+use crate::gadgets::resources::GameResources;
+use crate::gameplay::components::*;
+use crate::gameplay::events::*;
+use crate::gameplay::game_states::{AppState, LevelState};
+use crate::gameplay::resources::{
+    PendingBallClear, RunModifiers, RunRng, RunSeed, ShopRerolls,
+};
+use crate::simulation::resources::*;
+use avian2d::prelude::*;
+use bevy::prelude::*;
+use bevy_rand::prelude::{Entropy, WyRand};
+use rand::prelude::*;
+use std::f32::consts::TAU;
+
+pub fn load_headless_assets(
+    asset_server: Res<AssetServer>,
+    mut game_resources: ResMut<GameResources>,
+) {
+    game_resources.setup_headless(&asset_server);
+}
+
+/// Starts the next run with a fresh seed, or writes the stats once all runs are done.
+pub fn start_next_run_system(
+    mut commands: Commands,
+    config: Res<SimulationConfig>,
+    stats: Res<SimulationStats>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut exit_events: EventWriter<AppExit>,
+) {
+    if stats.current_run >= config.runs {
+        exit_events.write(write_stats(&config, &stats));
+        return;
+    }
+    if stats.current_run % 100 == 0 {
+        info!("Simulating run {} of {}", stats.current_run + 1, config.runs);
+    }
+
+    let run_seed = RunSeed::from_text(&format!("{}-{}", config.seed, stats.current_run));
+    commands.insert_resource(RunRng::from_seed(&run_seed));
+    commands.insert_resource(run_seed);
+    commands.insert_resource(RunModifiers::default());
+    next_state.set(AppState::InGame);
+}
+
+fn write_stats(config: &SimulationConfig, stats: &SimulationStats) -> AppExit {
+    let files = [
+        ("rounds.csv", stats.rounds_csv()),
+        ("runs.csv", stats.runs_csv()),
+        ("coins.csv", stats.coins_csv()),
+    ];
+    if let Err(error) = std::fs::create_dir_all(&config.output_dir) {
+        error!("could not create {}: {error}", config.output_dir.display());
+        return AppExit::error();
+    }
+    for (file_name, csv) in files {
+        let path = config.output_dir.join(file_name);
+        if let Err(error) = std::fs::write(&path, csv) {
+            error!("could not write {}: {error}", path.display());
+            return AppExit::error();
+        }
+    }
+    info!(
+        "Simulated {} runs, stats written to {}",
+        config.runs,
+        config.output_dir.display()
+    );
+    AppExit::Success
+}
+
+pub fn fill_hand_system(mut player: Single<&mut Player>, mut run_rng: ResMut<RunRng>) {
+    player.fill_hand(&mut run_rng.deck);
+}
+
+pub fn pick_card_system(
+    mut commands: Commands,
+    player: Single<&Player>,
+    q_placed: Query<(), With<PlayerPlacedGadget>>,
+    mut policy_rng: ResMut<PolicyRng>,
+) {
+    let has_placed_gadget = !q_placed.is_empty();
+    if let Some(card) = player
+        .current_hand
+        .iter()
+        .filter(|card| player.is_card_playable(card, has_placed_gadget))
+        .choose(&mut policy_rng.0)
+    {
+        commands.trigger(OnGadgetCardSelected::new(*card));
+    }
+}
+
+/// Tries a few random spots and places the gadget on the first one that is free.
+pub fn place_gadget_system(
+    mut commands: Commands,
+    player: Single<&Player>,
+    q_gadget: Query<(&Collider, &CollisionLayers, Has<CanBeRotated>)>,
+    spatial_query: SpatialQuery,
+    game_resources: Res<GameResources>,
+    mut policy_rng: ResMut<PolicyRng>,
+) {
+    let Some(current_widget) = player.current_widget else {
+        return;
+    };
+    // the collider is added by the gadget's hook, it is there a frame later
+    let Ok((collider, layers, can_be_rotated)) = q_gadget.get(current_widget) else {
+        return;
+    };
+    let filter = SpatialQueryFilter::from_mask(layers.filters);
+
+    let mut placement = None;
+    for _ in 0..20 {
+        let position = game_resources.get_random_position_in_play_area(&mut policy_rng.0);
+        let rotation = if can_be_rotated {
+            policy_rng.0.random_range(0.0..TAU)
+        } else {
+            0.0
+        };
+        placement = Some((position, rotation));
+        let is_intersecting = spatial_query
+            .shape_intersections(collider, position, rotation, &filter)
+            .into_iter()
+            .any(|entity| entity != current_widget);
+        if !is_intersecting {
+            break;
+        }
+    }
+
+    if let Some((position, rotation)) = placement {
+        commands.trigger(RequestToPlaceGadget::new(position.extend(0.0), rotation));
+    }
+}
+
+pub fn pick_placed_gadget_system(
+    mut commands: Commands,
+    q_placed: Query<(Entity, Option<&SpawnedFromCard>), With<PlayerPlacedGadget>>,
+    game_resources: Res<GameResources>,
+    state: Res<State<LevelState>>,
+    mut stats: ResMut<SimulationStats>,
+    mut policy_rng: ResMut<PolicyRng>,
+) {
+    let picked = q_placed.iter().choose(&mut policy_rng.0);
+    // skip about half of the time, it is not always worth it to give up a gadget
+    let gadget = picked.filter(|_| policy_rng.0.random_bool(0.5));
+
+    if let Some((_, Some(spawned_from_card))) = gadget {
+        if matches!(state.get(), LevelState::RecycleGadget) {
+            let price = game_resources.get_price_per_card(&spawned_from_card.0);
+            stats.add_coins(Some(spawned_from_card.0), price);
+        }
+    }
+    commands.trigger(RequestToPickPlacedGadget {
+        gadget: gadget.map(|(entity, _)| entity),
+    });
+}
+
+/// Fires with a random power and aim, like a player who never charges the same way twice.
+pub fn fire_cannon_system(
+    mut commands: Commands,
+    cannon: Single<&BallCannon>,
+    mut policy_rng: ResMut<PolicyRng>,
+) {
+    let aim = policy_rng
+        .0
+        .random_range(-BallCannon::AIM_ARC..=BallCannon::AIM_ARC);
+    commands.trigger(RequestToFireCannon {
+        power: policy_rng.0.random_range(cannon.min_power..=cannon.max_power),
+        angle: cannon.base_angle + aim,
+    });
+}
+
+pub fn continue_round_system(mut commands: Commands) {
+    commands.trigger(RequestToContinueRound);
+}
+
+pub fn start_bouncing_timeout(mut commands: Commands, config: Res<SimulationConfig>) {
+    commands.insert_resource(BouncingTimeout(Timer::new(
+        config.max_bouncing_time,
+        TimerMode::Once,
+    )));
+}
+
+pub fn bouncing_timeout_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut timeout: ResMut<BouncingTimeout>,
+) {
+    if timeout.0.tick(time.delta()).just_finished() {
+        commands.insert_resource(PendingBallClear);
+    }
+}
+
+pub fn stock_shop_system(
+    mut commands: Commands,
+    mut player: Single<&mut Player>,
+    game_resources: Res<GameResources>,
+    mut run_rng: ResMut<RunRng>,
+    run_modifiers: Res<RunModifiers>,
+) {
+    let offers =
+        player.roll_shop_offers(&game_resources, &run_modifiers.banned_cards, &mut run_rng.shop);
+    commands.insert_resource(ShopOffers(offers));
+}
+
+/// Buys a random selection of the affordable offers, sometimes rerolls them and stays for the new
+/// ones, otherwise sometimes pays for a service and leaves the shop.
+pub fn shop_system(
+    mut commands: Commands,
+    mut player: Single<&mut Player>,
+    mut offers: ResMut<ShopOffers>,
+    game_resources: Res<GameResources>,
+    shop_rerolls: Res<ShopRerolls>,
+    mut run_rng: ResMut<RunRng>,
+    mut policy_rng: ResMut<PolicyRng>,
+    mut next_state: ResMut<NextState<LevelState>>,
+) {
+    offers.0.shuffle(&mut policy_rng.0);
+    for card in offers.0.drain(..) {
+        let price = game_resources.get_price_per_card(&card);
+        if price > player.coins || !policy_rng.0.random_bool(0.5) {
+            continue;
+        }
+        player.coins -= price;
+        player.receive_card(card, &game_resources, &mut run_rng.deck);
+    }
+
+    let reroll_price = game_resources.get_reroll_price(shop_rerolls.0);
+    if reroll_price <= player.coins && policy_rng.0.random_bool(0.3) {
+        commands.trigger(RequestToRerollShop);
+        return;
+    }
+
+    let service = if policy_rng.0.random_bool(0.5) {
+        ShopService::RemoveCard
+    } else {
+        ShopService::UpgradeCard
+    };
+    let card = player.full_deck().copied().choose(&mut policy_rng.0);
+    if let Some(card) = card.filter(|_| policy_rng.0.random_bool(0.3)) {
+        // the request checks the price and whether the card can take the service
+        commands.trigger(RequestShopService { service, card });
+    }
+    next_state.set(LevelState::WidgetSelection);
+}
+
+/// Only the offers that cost something are replaced, the free ones of this visit are gone already.
+pub fn restock_shop_system(
+    _: Trigger<OnShopRerolled>,
+    player: Single<&Player>,
+    mut offers: ResMut<ShopOffers>,
+    game_resources: Res<GameResources>,
+    mut run_rng: ResMut<RunRng>,
+    run_modifiers: Res<RunModifiers>,
+) {
+    offers.0 = player
+        .reroll_shop_offers(&game_resources, &run_modifiers.banned_cards, &mut run_rng.shop)
+        .into_iter()
+        .filter(|card| game_resources.get_price_per_card(card) > 0)
+        .collect();
+}
+
+pub fn count_collected_coins(
+    trigger: Trigger<OnCoinCollected>,
+    q_coin: Query<Option<&CoinSource>>,
+    mut stats: ResMut<SimulationStats>,
+) {
+    let source = q_coin
+        .get(trigger.target())
+        .ok()
+        .flatten()
+        .map(|coin_source| coin_source.0);
+    stats.add_coins(source, 1);
+}
+
+pub fn record_round_system(player: Single<&Player>, mut stats: ResMut<SimulationStats>) {
+    let round_stats = RoundStats {
+        run: stats.current_run,
+        round: stats.current_round,
+        level: player.current_level,
+        points: player.points_last_round,
+        total_points: player.points,
+        coins: player.coins,
+        balls_left: player.balls_left,
+    };
+    stats.rounds.push(round_stats);
+    stats.current_round += 1;
+}
+
+pub fn record_run_system(
+    player: Single<&Player>,
+    run_seed: Res<RunSeed>,
+    mut stats: ResMut<SimulationStats>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let run_stats = RunStats {
+        run: stats.current_run,
+        seed: run_seed.text.clone(),
+        level_reached: player.current_level,
+        points: player.points,
+        rounds: stats.current_round,
+        coins_per_source: std::mem::take(&mut stats.current_coins),
+    };
+    stats.runs.push(run_stats);
+    stats.current_run += 1;
+    stats.current_round = 0;
+    next_state.set(AppState::Startup);
+}
+
+pub fn seed_policy_rng(mut commands: Commands, config: Res<SimulationConfig>) {
+    let seed = RunSeed::from_text(&config.seed);
+    commands.insert_resource(PolicyRng(Entropy::<WyRand>::seed_from_u64(!seed.value)));
+}